@@ -0,0 +1,444 @@
+use crate::lesser::config::config_dir;
+use crate::lesser::formats::Message;
+use crate::lesser::pattern::CaseMode;
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+use termion::event::Key;
+
+/// What a key does. Most of the actions are a Message, the others are handled by the key thread
+/// because they need more keys: the prompts and the marks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Action {
+    Help,
+    Exit,
+    Suspend,
+    ScrollDown,
+    ScrollUp,
+    ScrollDownPage,
+    ScrollUpPage,
+    ScrollDownWindow,
+    ScrollUpWindow,
+    ScrollDownHalfPage,
+    ScrollUpHalfPage,
+    ScrollLeft,
+    ScrollRight,
+    ScrollToBeginning,
+    ScrollToEnd,
+    GotoPercent,
+    SetMark,
+    GotoMark,
+    Search,
+    SearchBackward,
+    SearchNext,
+    SearchPrevious,
+    Filter,
+    Command,
+    Save,
+    ToggleSmartCase,
+    ToggleIgnoreCase,
+}
+
+impl Action {
+    /// The message sent for the action, with the count typed before the key.
+    pub(crate) fn message(self, count: Option<u64>) -> Option<Message> {
+        Some(match self {
+            Action::Help => Message::Help,
+            Action::Exit => Message::Exit,
+            Action::Suspend => Message::Suspend,
+            Action::ScrollDown => Message::ScrollDown,
+            Action::ScrollUp => Message::ScrollUp,
+            Action::ScrollDownPage => Message::ScrollDownPage,
+            Action::ScrollUpPage => Message::ScrollUpPage,
+            Action::ScrollDownWindow => Message::ScrollDownWindow(count),
+            Action::ScrollUpWindow => Message::ScrollUpWindow(count),
+            Action::ScrollDownHalfPage => Message::ScrollDownHalfPage(count),
+            Action::ScrollUpHalfPage => Message::ScrollUpHalfPage(count),
+            Action::ScrollLeft => Message::ScrollLeft,
+            Action::ScrollRight => Message::ScrollRight,
+            Action::ScrollToBeginning => Message::ScrollToBeginning,
+            Action::ScrollToEnd => Message::ScrollToEnd,
+            Action::GotoPercent => Message::GotoPercent(count.unwrap_or(0)),
+            Action::SearchNext => Message::SearchNext,
+            Action::SearchPrevious => Message::SearchPrevious,
+            Action::ToggleSmartCase => Message::ToggleCase(CaseMode::Smart),
+            Action::ToggleIgnoreCase => Message::ToggleCase(CaseMode::Insensitive),
+            Action::SetMark
+            | Action::GotoMark
+            | Action::Search
+            | Action::SearchBackward
+            | Action::Filter
+            | Action::Command
+            | Action::Save => return None,
+        })
+    }
+
+    fn from_name(name: &str) -> Option<Action> {
+        ACTION_NAMES
+            .iter()
+            .find(|(_, action_name)| *action_name == name)
+            .map(|(action, _)| *action)
+    }
+
+    /// The character shown in the prompt, for the actions opening it.
+    pub(crate) fn prompt(self) -> Option<char> {
+        match self {
+            Action::Search => Some('/'),
+            Action::SearchBackward => Some('?'),
+            Action::Filter => Some('&'),
+            Action::Command => Some(':'),
+            Action::Save => Some('s'),
+            _ => None,
+        }
+    }
+
+    fn description(self) -> &'static str {
+        match self {
+            Action::Help => "Show this help. The keys for help or quit go back to the file",
+            Action::Exit => "Exit",
+            Action::Suspend => "Suspend. The screen is restored when resumed with `fg`",
+            Action::ScrollDown => "Move down one row",
+            Action::ScrollUp => "Move up one row",
+            Action::ScrollDownPage => "Move down one page",
+            Action::ScrollUpPage => "Move up one page",
+            Action::ScrollDownWindow => "Move down one page. N sets the page to N rows",
+            Action::ScrollUpWindow => "Move up one page. N sets the page to N rows",
+            Action::ScrollDownHalfPage => "Move down half a page. N sets the half page to N rows",
+            Action::ScrollUpHalfPage => "Move up half a page. N sets the half page to N rows",
+            Action::ScrollLeft => "Move left ten columns",
+            Action::ScrollRight => "Move right ten columns",
+            Action::ScrollToBeginning => "Go to the beginning of the file",
+            Action::ScrollToEnd => "Go to the end of the file",
+            Action::GotoPercent => "Go to N percent of the file",
+            Action::SetMark => "Mark the current position with the letter typed next",
+            Action::GotoMark => "Go to the mark typed next. ' is the position before the jump",
+            Action::Search => "Search forward. An empty pattern repeats the last search",
+            Action::SearchBackward => "Search backward",
+            Action::SearchNext => "Repeat the last search",
+            Action::SearchPrevious => "Repeat the last search in the opposite direction",
+            Action::Filter => "Show only the lines matching. !pattern shows the others",
+            Action::Command => "Run a command. `b N` goes to the byte at offset N",
+            Action::Save => {
+                "Save to a file. `N,M file` saves the lines N to M, `'a,'b file` between marks"
+            }
+            Action::ToggleSmartCase => "Ignore the case in searches without uppercase letters",
+            Action::ToggleIgnoreCase => "Ignore the case in searches",
+        }
+    }
+}
+
+/// Names of the actions, used in the config file.
+const ACTION_NAMES: &[(Action, &str)] = &[
+    (Action::Help, "help"),
+    (Action::Exit, "quit"),
+    (Action::Suspend, "suspend"),
+    (Action::ScrollDown, "down"),
+    (Action::ScrollUp, "up"),
+    (Action::ScrollDownPage, "page-down"),
+    (Action::ScrollUpPage, "page-up"),
+    (Action::ScrollDownWindow, "window-down"),
+    (Action::ScrollUpWindow, "window-up"),
+    (Action::ScrollDownHalfPage, "half-page-down"),
+    (Action::ScrollUpHalfPage, "half-page-up"),
+    (Action::ScrollLeft, "left"),
+    (Action::ScrollRight, "right"),
+    (Action::ScrollToBeginning, "beginning"),
+    (Action::ScrollToEnd, "end"),
+    (Action::GotoPercent, "percent"),
+    (Action::SetMark, "set-mark"),
+    (Action::GotoMark, "goto-mark"),
+    (Action::Search, "search"),
+    (Action::SearchBackward, "search-backward"),
+    (Action::SearchNext, "search-next"),
+    (Action::SearchPrevious, "search-previous"),
+    (Action::Filter, "filter"),
+    (Action::Command, "command"),
+    (Action::Save, "save"),
+    (Action::ToggleSmartCase, "toggle-smart-case"),
+    (Action::ToggleIgnoreCase, "toggle-ignore-case"),
+];
+/// Action name removing a binding.
+const NO_ACTION: &str = "none";
+
+/// The keys and their action, in the order shown by the help.
+const DEFAULT_KEY_BINDINGS: &[(&[Key], Action)] = &[
+    (&[Key::Char('h')], Action::Help),
+    (&[Key::Char('H')], Action::Help),
+    (&[Key::Char('q')], Action::Exit),
+    // In raw mode, Ctrl-Z doesn't send SIGTSTP.
+    (&[Key::Ctrl('z')], Action::Suspend),
+    (&[Key::Char('j')], Action::ScrollDown),
+    (&[Key::Char('e')], Action::ScrollDown),
+    (&[Key::Char('\n')], Action::ScrollDown),
+    (&[Key::Down], Action::ScrollDown),
+    (&[Key::Char('k')], Action::ScrollUp),
+    (&[Key::Char('y')], Action::ScrollUp),
+    (&[Key::Up], Action::ScrollUp),
+    (&[Key::Char(' ')], Action::ScrollDownPage),
+    (&[Key::Char('f')], Action::ScrollDownPage),
+    (&[Key::PageDown], Action::ScrollDownPage),
+    (&[Key::Char('b')], Action::ScrollUpPage),
+    (&[Key::PageUp], Action::ScrollUpPage),
+    (&[Key::Char('z')], Action::ScrollDownWindow),
+    (&[Key::Char('w')], Action::ScrollUpWindow),
+    (&[Key::Char('d')], Action::ScrollDownHalfPage),
+    (&[Key::Ctrl('d')], Action::ScrollDownHalfPage),
+    (&[Key::Char('u')], Action::ScrollUpHalfPage),
+    (&[Key::Ctrl('u')], Action::ScrollUpHalfPage),
+    (&[Key::Left], Action::ScrollLeft),
+    (&[Key::Right], Action::ScrollRight),
+    (&[Key::Char('g')], Action::ScrollToBeginning),
+    (&[Key::Home], Action::ScrollToBeginning),
+    (&[Key::Char('G')], Action::ScrollToEnd),
+    (&[Key::End], Action::ScrollToEnd),
+    (&[Key::Char('p')], Action::GotoPercent),
+    (&[Key::Char('%')], Action::GotoPercent),
+    (&[Key::Char('m')], Action::SetMark),
+    (&[Key::Char('\'')], Action::GotoMark),
+    (&[Key::Char('/')], Action::Search),
+    (&[Key::Char('?')], Action::SearchBackward),
+    (&[Key::Char('n')], Action::SearchNext),
+    (&[Key::Char('N')], Action::SearchPrevious),
+    (&[Key::Char('&')], Action::Filter),
+    (&[Key::Char(':')], Action::Command),
+    (&[Key::Char('s')], Action::Save),
+    (&[Key::Char('-'), Key::Char('i')], Action::ToggleSmartCase),
+    (&[Key::Char('-'), Key::Char('I')], Action::ToggleIgnoreCase),
+];
+
+/// Result of looking up the keys typed so far.
+#[derive(Debug, PartialEq)]
+pub(crate) enum Lookup {
+    /// The keys are bound to the action.
+    Action(Action),
+    /// The keys start a longer sequence, more keys are needed. They can be bound as well, e.g.
+    /// `:` when `:n` is bound.
+    Prefix,
+    None,
+}
+
+/// Sequences of keys and their action. The defaults can be changed in a TOML file, e.g.:
+/// ```toml
+/// [keys]
+/// "gg" = "beginning"
+/// "<C-f>" = "page-down"
+/// "d" = "none"
+/// ```
+/// Special keys are written in angle brackets, as in vim.
+pub(crate) struct KeyBindings {
+    bindings: Vec<(Vec<Key>, Action)>,
+}
+
+impl KeyBindings {
+    pub(crate) fn new() -> KeyBindings {
+        KeyBindings {
+            bindings: DEFAULT_KEY_BINDINGS
+                .iter()
+                .map(|(keys, action)| (keys.to_vec(), *action))
+                .collect(),
+        }
+    }
+
+    /// `$XDG_CONFIG_HOME/lesser/keys.toml`, or `~/.config/lesser/keys.toml`.
+    pub(crate) fn default_path() -> Option<PathBuf> {
+        config_dir().map(|dir| dir.join("keys.toml"))
+    }
+
+    /// The default bindings, changed by the file if it exists.
+    pub(crate) fn load(path: &Path) -> io::Result<KeyBindings> {
+        let mut bindings = KeyBindings::new();
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(error) if error.kind() == ErrorKind::NotFound => return Ok(bindings),
+            Err(error) => return Err(error),
+        };
+        bindings.apply(&content).map_err(|error| {
+            io::Error::new(
+                ErrorKind::InvalidData,
+                format!("{}: {}", path.display(), error),
+            )
+        })?;
+        Ok(bindings)
+    }
+
+    /// Changes the bindings with the `keys` table of a TOML config.
+    pub(crate) fn apply(&mut self, content: &str) -> Result<(), String> {
+        let config: toml::Value = content.parse().map_err(|error| format!("{}", error))?;
+        let keys = match config.get("keys") {
+            Some(toml::Value::Table(keys)) => keys,
+            Some(_) => return Err("keys must be a table".to_string()),
+            None => return Ok(()),
+        };
+        for (sequence, action) in keys {
+            let sequence = parse_keys(sequence)?;
+            let action = match action.as_str() {
+                Some(NO_ACTION) => None,
+                Some(name) => Some(
+                    Action::from_name(name).ok_or_else(|| format!("unknown action {}", name))?,
+                ),
+                None => return Err(format!("the action of {} must be a string", action)),
+            };
+            self.bindings.retain(|(bound, _)| *bound != sequence);
+            if let Some(action) = action {
+                self.bindings.push((sequence, action));
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn lookup(&self, keys: &[Key]) -> Lookup {
+        let is_prefix = self
+            .bindings
+            .iter()
+            .any(|(sequence, _)| sequence.len() > keys.len() && sequence.starts_with(keys));
+        match self.action(keys) {
+            _ if is_prefix => Lookup::Prefix,
+            Some(action) => Lookup::Action(action),
+            None => Lookup::None,
+        }
+    }
+
+    /// The action bound to exactly these keys.
+    pub(crate) fn action(&self, keys: &[Key]) -> Option<Action> {
+        self.bindings
+            .iter()
+            .find(|(sequence, _)| sequence == keys)
+            .map(|(_, action)| *action)
+    }
+
+    /// The help page: each action with its keys, in the order of the bindings.
+    pub(crate) fn help_text(&self) -> String {
+        let mut actions: Vec<(Action, Vec<String>)> = vec![];
+        for (sequence, action) in &self.bindings {
+            let name: String = sequence.iter().map(|key| key_name(*key)).collect();
+            match actions.iter_mut().find(|(a, _)| a == action) {
+                Some((_, names)) => names.push(name),
+                None => actions.push((*action, vec![name])),
+            }
+        }
+        let mut help = String::from("Keys, N is an optional number typed before the key:\n\n");
+        for (action, names) in actions {
+            help.push_str(&format!(
+                "  {:<20} {}\n",
+                names.join(" "),
+                action.description()
+            ));
+        }
+        help.push_str(
+            "\nWith --mouse, the wheel scrolls and clicking a line moves it to the top, marked \
+             with #.\n",
+        );
+        help
+    }
+}
+
+/// Parses a sequence of keys: characters, or special keys in angle brackets like `<C-d>`.
+fn parse_keys(sequence: &str) -> Result<Vec<Key>, String> {
+    let mut keys = vec![];
+    let mut chars = sequence.chars();
+    while let Some(c) = chars.next() {
+        if c != '<' {
+            keys.push(Key::Char(c));
+            continue;
+        }
+        let name: String = chars.by_ref().take_while(|c| *c != '>').collect();
+        let key = parse_special_key(&name)
+            .ok_or_else(|| format!("unknown key <{}> in {}", name, sequence))?;
+        keys.push(key);
+    }
+    if keys.is_empty() {
+        return Err("empty key sequence".to_string());
+    }
+    Ok(keys)
+}
+
+fn parse_special_key(name: &str) -> Option<Key> {
+    let mut chars = name.chars();
+    if let (Some(modifier), Some('-'), Some(c), None) =
+        (chars.next(), chars.next(), chars.next(), chars.next())
+    {
+        match modifier.to_ascii_uppercase() {
+            'C' => return Some(Key::Ctrl(c.to_ascii_lowercase())),
+            'A' | 'M' => return Some(Key::Alt(c)),
+            _ => {}
+        }
+    }
+    Some(match name.to_ascii_lowercase().as_str() {
+        "space" => Key::Char(' '),
+        "enter" | "cr" => Key::Char('\n'),
+        "tab" => Key::Char('\t'),
+        "lt" => Key::Char('<'),
+        "esc" => Key::Esc,
+        "bs" => Key::Backspace,
+        "del" => Key::Delete,
+        "up" => Key::Up,
+        "down" => Key::Down,
+        "left" => Key::Left,
+        "right" => Key::Right,
+        "pageup" => Key::PageUp,
+        "pagedown" => Key::PageDown,
+        "home" => Key::Home,
+        "end" => Key::End,
+        function => Key::F(function.strip_prefix('f')?.parse().ok()?),
+    })
+}
+
+/// The key as written in the config file.
+fn key_name(key: Key) -> String {
+    match key {
+        Key::Char(' ') => "<Space>".to_string(),
+        Key::Char('\n') => "<Enter>".to_string(),
+        Key::Char('\t') => "<Tab>".to_string(),
+        Key::Char('<') => "<lt>".to_string(),
+        Key::Char(c) => c.to_string(),
+        Key::Ctrl(c) => format!("<C-{}>", c),
+        Key::Alt(c) => format!("<A-{}>", c),
+        Key::F(n) => format!("<F{}>", n),
+        Key::Esc => "<Esc>".to_string(),
+        Key::Backspace => "<BS>".to_string(),
+        Key::Delete => "<Del>".to_string(),
+        key => format!("<{:?}>", key),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lesser::bindings::{parse_keys, Action, KeyBindings, Lookup};
+    use termion::event::Key;
+
+    #[test]
+    fn test_parse_keys() {
+        assert_eq!(Ok(vec![Key::Char('g'), Key::Char('g')]), parse_keys("gg"));
+        assert_eq!(
+            Ok(vec![
+                Key::Ctrl('f'),
+                Key::PageDown,
+                Key::Char('<'),
+                Key::F(5)
+            ]),
+            parse_keys("<C-F><PageDown><lt><F5>")
+        );
+        assert!(parse_keys("<Nope>").is_err());
+        assert!(parse_keys("").is_err());
+    }
+
+    #[test]
+    fn test_key_bindings() {
+        let mut bindings = KeyBindings::new();
+        bindings
+            .apply("[keys]\n\"gg\" = \"beginning\"\n\"g\" = \"none\"\n\":n\" = \"end\"\n")
+            .unwrap();
+        assert!(bindings.apply("[keys]\n\"x\" = \"fly\"\n").is_err());
+        assert_eq!(Lookup::Prefix, bindings.lookup(&[Key::Char('g')]));
+        assert_eq!(
+            Lookup::Action(Action::ScrollToBeginning),
+            bindings.lookup(&[Key::Char('g'), Key::Char('g')])
+        );
+        assert_eq!(Lookup::Prefix, bindings.lookup(&[Key::Char(':')]));
+        assert_eq!(Some(Action::Command), bindings.action(&[Key::Char(':')]));
+        assert_eq!(Lookup::None, bindings.lookup(&[Key::Char('l')]));
+        let help = bindings.help_text();
+        assert!(help.contains("  j e <Enter> <Down>   Move down one row\n"));
+        assert!(help.contains("  <Home> gg            Go to the beginning"));
+    }
+}
@@ -0,0 +1,303 @@
+use crate::lesser::encoding::Encoding;
+use crate::lesser::pattern::CaseMode;
+use crate::lesser::screen_move_handler::JumpTarget;
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::{env, fs};
+
+/// Options set by a source: the config file, the `LESS` or `LESSER` environment variables or
+/// the command line. None for the options not set, so that the sources can be merged.
+#[derive(Debug, Default, PartialEq)]
+pub struct Config {
+    pub history: Option<bool>,
+    pub line_numbers: Option<bool>,
+    pub mouse: Option<bool>,
+    pub quit_if_one_screen: Option<bool>,
+    pub alternate_screen: Option<bool>,
+    pub case: Option<CaseMode>,
+    pub squeeze_blank_lines: Option<bool>,
+    pub hex_bytes: Option<bool>,
+    pub tab_width: Option<usize>,
+    pub raw_colors: Option<bool>,
+    pub jump_target: Option<JumpTarget>,
+    pub hexdump: Option<bool>,
+    pub encoding: Option<Encoding>,
+}
+
+impl Config {
+    /// `$XDG_CONFIG_HOME/lesser/config.toml`, or `~/.config/lesser/config.toml`.
+    pub fn default_path() -> Option<PathBuf> {
+        config_dir().map(|dir| dir.join("config.toml"))
+    }
+
+    /// Reads the config file, with the options named like the long flags, e.g.:
+    /// ```toml
+    /// line-numbers = true
+    /// history = false
+    /// tab-width = 4
+    /// ```
+    /// A missing file sets no option. There is no option to wrap the long lines: they are
+    /// always chopped, and scrolled sideways.
+    pub fn load(path: &Path) -> io::Result<Config> {
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(error) if error.kind() == ErrorKind::NotFound => return Ok(Config::default()),
+            Err(error) => return Err(error),
+        };
+        Config::parse(&content).map_err(|error| {
+            io::Error::new(
+                ErrorKind::InvalidData,
+                format!("{}: {}", path.display(), error),
+            )
+        })
+    }
+
+    fn parse(content: &str) -> Result<Config, String> {
+        let table = match content.parse().map_err(|error| format!("{}", error))? {
+            toml::Value::Table(table) => table,
+            _ => return Err("expected a table".to_string()),
+        };
+        let mut config = Config::default();
+        for (name, value) in table {
+            match name.as_str() {
+                "encoding" => {
+                    let encoding = value.as_str().and_then(Encoding::from_name);
+                    config.encoding = Some(encoding.ok_or("unknown encoding")?);
+                    continue;
+                }
+                "tab-width" => {
+                    let tab_width = value.as_integer().filter(|width| *width > 0);
+                    let tab_width = tab_width.ok_or("tab-width must be a positive integer")?;
+                    config.tab_width = Some(tab_width as usize);
+                    continue;
+                }
+                // Like `-j`, e.g. `-2`, or a fraction of the screen, e.g. `0.5` or `".5"`.
+                "jump-target" => {
+                    let target = match value {
+                        toml::Value::Integer(line) => Some(JumpTarget::Line(line)),
+                        toml::Value::Float(fraction) => JumpTarget::parse(&fraction.to_string()),
+                        toml::Value::String(target) => JumpTarget::parse(&target),
+                        _ => None,
+                    };
+                    config.jump_target = Some(target.ok_or("invalid jump-target")?);
+                    continue;
+                }
+                _ => {}
+            }
+            let enabled = value
+                .as_bool()
+                .ok_or_else(|| format!("{} must be true or false", name))?;
+            let option = match name.as_str() {
+                "history" => &mut config.history,
+                "line-numbers" => &mut config.line_numbers,
+                "mouse" => &mut config.mouse,
+                "quit-if-one-screen" => &mut config.quit_if_one_screen,
+                "alternate-screen" => &mut config.alternate_screen,
+                "squeeze-blank-lines" => &mut config.squeeze_blank_lines,
+                "hex-bytes" => &mut config.hex_bytes,
+                "raw-colors" => &mut config.raw_colors,
+                "hexdump" => &mut config.hexdump,
+                "ignore-case" | "ignore-case-always" | "case-sensitive" => {
+                    config.case = Some(match (name.as_str(), enabled) {
+                        ("ignore-case", true) => CaseMode::Smart,
+                        ("ignore-case-always", true) => CaseMode::Insensitive,
+                        _ => CaseMode::Sensitive,
+                    });
+                    continue;
+                }
+                _ => return Err(format!("unknown option {}", name)),
+            };
+            *option = Some(enabled);
+        }
+        Ok(config)
+    }
+
+    /// The options set here, and the others from `other`.
+    pub fn or(self, other: Config) -> Config {
+        Config {
+            history: self.history.or(other.history),
+            line_numbers: self.line_numbers.or(other.line_numbers),
+            mouse: self.mouse.or(other.mouse),
+            quit_if_one_screen: self.quit_if_one_screen.or(other.quit_if_one_screen),
+            alternate_screen: self.alternate_screen.or(other.alternate_screen),
+            case: self.case.or(other.case),
+            squeeze_blank_lines: self.squeeze_blank_lines.or(other.squeeze_blank_lines),
+            hex_bytes: self.hex_bytes.or(other.hex_bytes),
+            tab_width: self.tab_width.or(other.tab_width),
+            raw_colors: self.raw_colors.or(other.raw_colors),
+            jump_target: self.jump_target.or(other.jump_target),
+            hexdump: self.hexdump.or(other.hexdump),
+            encoding: self.encoding.or(other.encoding),
+        }
+    }
+
+    /// The options of the `LESS` environment variable, e.g. `-FRX` or `-N --tabs=4`, with a
+    /// warning for each option that lesser doesn't understand.
+    pub fn from_less(value: &str) -> (Config, Vec<String>) {
+        let mut config = Config::default();
+        let mut warnings = vec![];
+        let mut tokens = value.split_whitespace();
+        while let Some(token) = tokens.next() {
+            if let Some(long) = token.strip_prefix("--") {
+                let mut parts = long.splitn(2, '=');
+                let name = parts.next().unwrap_or_default();
+                let result = match LESS_LONG_OPTIONS.iter().find(|(long, _)| *long == name) {
+                    Some((_, option)) => {
+                        let value = match option {
+                            'x' | 'j' => parts.next().or_else(|| tokens.next()),
+                            _ => None,
+                        };
+                        config.set_less_option(*option, value)
+                    }
+                    None => Err(format!("unknown option --{}", name)),
+                };
+                warnings.extend(result.err());
+                continue;
+            }
+            if token.starts_with('+') {
+                warnings.push(format!("commands like {} are not supported", token));
+                continue;
+            }
+            // Like less, the dash is optional.
+            let options = token.strip_prefix('-').unwrap_or(token);
+            for (i, option) in options.char_indices() {
+                if let 'x' | 'j' = option {
+                    let value = &options[i + 1..];
+                    let value = Some(value)
+                        .filter(|v| !v.is_empty())
+                        .or_else(|| tokens.next());
+                    warnings.extend(config.set_less_option(option, value).err());
+                    break;
+                }
+                warnings.extend(config.set_less_option(option, None).err());
+            }
+        }
+        (config, warnings)
+    }
+
+    fn set_less_option(&mut self, option: char, value: Option<&str>) -> Result<(), String> {
+        match option {
+            'F' => self.quit_if_one_screen = Some(true),
+            'X' => self.alternate_screen = Some(false),
+            'N' => self.line_numbers = Some(true),
+            'i' => self.case = Some(CaseMode::Smart),
+            'I' => self.case = Some(CaseMode::Insensitive),
+            's' => self.squeeze_blank_lines = Some(true),
+            'R' => self.raw_colors = Some(true),
+            // Long lines are always chopped.
+            'S' => {}
+            'x' => {
+                let value = value.unwrap_or_default();
+                match value.parse() {
+                    Ok(tab_width) if tab_width > 0 => self.tab_width = Some(tab_width),
+                    // Like `-x4,8`: the tab stops are always at the same distance.
+                    _ if value.contains(',') => {
+                        return Err(format!("-x{}: lists of tab stops are not supported", value))
+                    }
+                    _ => return Err(format!("-x{}: invalid tab width", value)),
+                }
+            }
+            'j' => {
+                let value = value.unwrap_or_default();
+                let target = JumpTarget::parse(value);
+                self.jump_target = Some(target.ok_or(format!("-j{}: invalid target", value))?);
+            }
+            _ => return Err(format!("unknown option -{}", option)),
+        }
+        Ok(())
+    }
+}
+
+/// The long options of less, for the short options understood.
+const LESS_LONG_OPTIONS: &[(&str, char)] = &[
+    ("quit-if-one-screen", 'F'),
+    ("RAW-CONTROL-CHARS", 'R'),
+    ("no-init", 'X'),
+    ("chop-long-lines", 'S'),
+    ("LINE-NUMBERS", 'N'),
+    ("ignore-case", 'i'),
+    ("IGNORE-CASE", 'I'),
+    ("squeeze-blank-lines", 's'),
+    ("tabs", 'x'),
+    ("jump-target", 'j'),
+];
+
+/// `$XDG_CONFIG_HOME/lesser`, or `~/.config/lesser`.
+pub(crate) fn config_dir() -> Option<PathBuf> {
+    env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .map(|dir| dir.join("lesser"))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lesser::config::Config;
+    use crate::lesser::encoding::Encoding;
+    use crate::lesser::pattern::CaseMode;
+    use crate::lesser::screen_move_handler::JumpTarget;
+
+    #[test]
+    fn test_parse_and_merge() {
+        let file = Config::parse(
+            "line-numbers = true\nmouse = true\nignore-case = true\nencoding = \"latin1\"\n\
+             tab-width = 4\nraw-colors = true\njump-target = 0.5\n",
+        )
+        .unwrap();
+        assert!(Config::parse("color = true\n").is_err());
+        assert!(Config::parse("mouse = 1\n").is_err());
+        assert!(Config::parse("tab-width = 0\n").is_err());
+        let env = Config {
+            mouse: Some(false),
+            ..Config::default()
+        };
+        assert_eq!(
+            Config {
+                line_numbers: Some(true),
+                mouse: Some(false),
+                case: Some(CaseMode::Smart),
+                tab_width: Some(4),
+                raw_colors: Some(true),
+                jump_target: Some(JumpTarget::Fraction(0.5)),
+                encoding: Some(Encoding::Latin1),
+                ..Config::default()
+            },
+            env.or(file)
+        );
+    }
+
+    #[test]
+    fn test_from_less() {
+        let (config, warnings) = Config::from_less("-FRX");
+        assert_eq!(
+            Config {
+                quit_if_one_screen: Some(true),
+                raw_colors: Some(true),
+                alternate_screen: Some(false),
+                ..Config::default()
+            },
+            config
+        );
+        assert!(warnings.is_empty());
+
+        let (config, warnings) = Config::from_less("NSis -x 4 --LINE-NUMBERS --jump-target=.5 -Q");
+        assert_eq!(Some(true), config.line_numbers);
+        assert_eq!(Some(CaseMode::Smart), config.case);
+        assert_eq!(Some(true), config.squeeze_blank_lines);
+        assert_eq!(Some(4), config.tab_width);
+        assert_eq!(Some(JumpTarget::Fraction(0.5)), config.jump_target);
+        assert_eq!(vec!["unknown option -Q"], warnings);
+
+        let (config, warnings) = Config::from_less("-j-2 -x4,8 --tabs=0");
+        assert_eq!(Some(JumpTarget::Line(-2)), config.jump_target);
+        assert_eq!(None, config.tab_width);
+        assert_eq!(
+            vec![
+                "-x4,8: lists of tab stops are not supported",
+                "-x0: invalid tab width"
+            ],
+            warnings
+        );
+    }
+}
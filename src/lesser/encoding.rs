@@ -0,0 +1,327 @@
+use crate::lesser::render::is_binary;
+
+/// Bytes looked at to guess the encoding.
+const SAMPLE_LEN: usize = 8192;
+const UTF8_BOM: &[u8] = b"\xef\xbb\xbf";
+const UTF16LE_BOM: &[u8] = b"\xff\xfe";
+const UTF16BE_BOM: &[u8] = b"\xfe\xff";
+/// Bytes of decoded text between two characters whose offset in the content is known.
+const CHECKPOINT_LEN: usize = 4096;
+
+/// Encoding of the content. The ones other than UTF-8 are decoded to UTF-8 before showing them.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Encoding {
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    /// ISO-8859-1: every byte is the code point with the same value.
+    Latin1,
+    /// Latin-1 with printable characters instead of most of the C1 controls, e.g. `€` for 0x80.
+    Windows1252,
+}
+
+/// The names of the encodings, the first one of each is shown in the status.
+pub(crate) const ENCODINGS: &[(Encoding, &str)] = &[
+    (Encoding::Utf8, "utf-8"),
+    (Encoding::Utf16Le, "utf-16le"),
+    (Encoding::Utf16Be, "utf-16be"),
+    (Encoding::Latin1, "latin1"),
+    (Encoding::Latin1, "iso-8859-1"),
+    (Encoding::Windows1252, "windows-1252"),
+    (Encoding::Windows1252, "cp1252"),
+];
+
+/// Characters of Windows-1252 from 0x80 to 0x9F. The bytes not used are the C1 controls, as in
+/// Latin-1.
+const WINDOWS_1252: [char; 32] = [
+    '€', '\u{81}', '‚', 'ƒ', '„', '…', '†', '‡', 'ˆ', '‰', 'Š', '‹', 'Œ', '\u{8d}', 'Ž', '\u{8f}',
+    '\u{90}', '‘', '’', '“', '”', '•', '–', '—', '˜', '™', 'š', '›', 'œ', '\u{9d}', 'ž', 'Ÿ',
+];
+
+impl Encoding {
+    pub fn from_name(name: &str) -> Option<Encoding> {
+        ENCODINGS
+            .iter()
+            .find(|(_encoding, encoding_name)| name.eq_ignore_ascii_case(encoding_name))
+            .map(|(encoding, _name)| *encoding)
+    }
+
+    pub(crate) fn name(self) -> &'static str {
+        ENCODINGS
+            .iter()
+            .find(|(encoding, _name)| *encoding == self)
+            .map_or("", |(_encoding, name)| name)
+    }
+
+    /// Guesses the encoding of the content: from the byte order mark if there is one, otherwise
+    /// UTF-16 if most of the characters are ASCII with a zero byte next to them, and Windows-1252
+    /// if most of the characters that aren't ASCII are invalid UTF-8. A few stray bytes in UTF-8
+    /// content are shown as such, instead of decoding all the other characters wrongly. Only an
+    /// encoding showing the content as text is chosen, so that the binary content stays as it is.
+    pub fn detect(content: &[u8]) -> Encoding {
+        if content.starts_with(UTF8_BOM) {
+            return Encoding::Utf8;
+        }
+        if content.starts_with(UTF16LE_BOM) {
+            return Encoding::Utf16Le;
+        }
+        if content.starts_with(UTF16BE_BOM) {
+            return Encoding::Utf16Be;
+        }
+        // An even length, not to cut a UTF-16 code unit.
+        let sample = &content[..content.len().min(SAMPLE_LEN) / 2 * 2];
+        let pairs = sample.len() / 2;
+        let ascii_with_zero = |zero: usize| {
+            sample
+                .chunks(2)
+                .filter(|pair| pair[zero] == 0 && pair[1 - zero] != 0)
+                .count()
+        };
+        let guess = if ascii_with_zero(1) * 2 > pairs {
+            Encoding::Utf16Le
+        } else if ascii_with_zero(0) * 2 > pairs {
+            Encoding::Utf16Be
+        } else if is_mostly_invalid_utf8(sample) {
+            Encoding::Windows1252
+        } else {
+            return Encoding::Utf8;
+        };
+        if is_binary(guess.decode(sample).as_bytes()) {
+            Encoding::Utf8
+        } else {
+            guess
+        }
+    }
+
+    /// The content as UTF-8, without the byte order mark. The invalid characters are replaced
+    /// by `�`.
+    pub(crate) fn decode(self, content: &[u8]) -> String {
+        let content = &content[self.bom_len(content)..];
+        self.chars(content).map(|(c, _len)| c).collect()
+    }
+
+    /// Like decode, with the offsets in the content of some of the characters, to find where a
+    /// part of the text comes from.
+    pub(crate) fn decode_with_offsets(self, content: &[u8]) -> (String, Offsets) {
+        let mut file_offset = self.bom_len(content);
+        let mut text = String::new();
+        let mut checkpoints = vec![];
+        for (c, len) in self.chars(&content[file_offset..]) {
+            if text.len() >= checkpoints.len() * CHECKPOINT_LEN {
+                checkpoints.push((text.len(), file_offset));
+            }
+            text.push(c);
+            file_offset += len;
+        }
+        let offsets = Offsets {
+            encoding: self,
+            checkpoints,
+        };
+        (text, offsets)
+    }
+
+    fn bom_len(self, content: &[u8]) -> usize {
+        let bom = match self {
+            Encoding::Utf8 => UTF8_BOM,
+            Encoding::Utf16Le => UTF16LE_BOM,
+            Encoding::Utf16Be => UTF16BE_BOM,
+            Encoding::Latin1 | Encoding::Windows1252 => return 0,
+        };
+        if content.starts_with(bom) {
+            bom.len()
+        } else {
+            0
+        }
+    }
+
+    /// The characters of the content, each with the number of bytes it takes in the content.
+    fn chars<'a>(self, content: &'a [u8]) -> Box<dyn Iterator<Item = (char, usize)> + 'a> {
+        match self {
+            Encoding::Utf8 => Box::new(content.utf8_chunks().flat_map(|chunk| {
+                let valid = chunk.valid().chars().map(|c| (c, c.len_utf8()));
+                let invalid = Some((char::REPLACEMENT_CHARACTER, chunk.invalid().len()))
+                    .filter(|(_c, len)| *len > 0);
+                valid.chain(invalid)
+            })),
+            Encoding::Utf16Le | Encoding::Utf16Be => {
+                let units = content.chunks_exact(2).map(move |pair| {
+                    let pair = [pair[0], pair[1]];
+                    if self == Encoding::Utf16Le {
+                        u16::from_le_bytes(pair)
+                    } else {
+                        u16::from_be_bytes(pair)
+                    }
+                });
+                let chars = char::decode_utf16(units).map(|c| match c {
+                    Ok(c) => (c, c.len_utf16() * 2),
+                    Err(_) => (char::REPLACEMENT_CHARACTER, 2),
+                });
+                // A code unit cut at the end.
+                let cut = Some((char::REPLACEMENT_CHARACTER, 1)).filter(|_| content.len() % 2 == 1);
+                Box::new(chars.chain(cut))
+            }
+            Encoding::Latin1 => Box::new(content.iter().map(|byte| (*byte as char, 1))),
+            Encoding::Windows1252 => Box::new(content.iter().map(|byte| {
+                let c = match byte {
+                    0x80..=0x9f => WINDOWS_1252[(byte - 0x80) as usize],
+                    _ => *byte as char,
+                };
+                (c, 1)
+            })),
+        }
+    }
+}
+
+/// Goes from the offsets in the content of a file to the ones in its decoded text, and back.
+pub(crate) struct Offsets {
+    encoding: Encoding,
+    /// (offset in the text, offset in the content) of a character every CHECKPOINT_LEN bytes of
+    /// text. The characters in between are decoded again to find the offsets.
+    checkpoints: Vec<(usize, usize)>,
+}
+
+impl Offsets {
+    /// Offset in the text of the character at `file_offset` in the content.
+    pub(crate) fn text_offset(&self, content: &[u8], file_offset: usize) -> usize {
+        let checkpoint = self
+            .checkpoints
+            .partition_point(|(_text, file)| *file <= file_offset)
+            .saturating_sub(1);
+        let (mut text, mut file) = self
+            .checkpoints
+            .get(checkpoint)
+            .cloned()
+            .unwrap_or_default();
+        for (c, len) in self.encoding.chars(&content[file..]) {
+            if file + len > file_offset {
+                break;
+            }
+            text += c.len_utf8();
+            file += len;
+        }
+        text
+    }
+
+    /// Offset in the content of the character at `text_offset` in the text.
+    pub(crate) fn file_offset(&self, content: &[u8], text_offset: usize) -> usize {
+        let checkpoint = self
+            .checkpoints
+            .partition_point(|(text, _file)| *text <= text_offset)
+            .saturating_sub(1);
+        let (mut text, mut file) = self
+            .checkpoints
+            .get(checkpoint)
+            .cloned()
+            .unwrap_or_default();
+        for (c, len) in self.encoding.chars(&content[file..]) {
+            if text + c.len_utf8() > text_offset {
+                break;
+            }
+            text += c.len_utf8();
+            file += len;
+        }
+        file
+    }
+}
+
+/// Whether the content has invalid UTF-8 sequences, and more of them than valid characters of
+/// several bytes. A character cut at the end of the content is not counted.
+fn is_mostly_invalid_utf8(mut content: &[u8]) -> bool {
+    let mut multibyte = 0;
+    let mut invalid = 0;
+    while !content.is_empty() {
+        let (valid_len, invalid_len) = match std::str::from_utf8(content) {
+            Ok(_) => (content.len(), 0),
+            Err(error) => (error.valid_up_to(), error.error_len().unwrap_or(0)),
+        };
+        let valid = std::str::from_utf8(&content[..valid_len]).unwrap_or_default();
+        multibyte += valid.chars().filter(|c| !c.is_ascii()).count();
+        if invalid_len == 0 {
+            break;
+        }
+        invalid += 1;
+        content = &content[valid_len + invalid_len..];
+    }
+    invalid > multibyte
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lesser::encoding::Encoding;
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(Encoding::Utf16Le, Encoding::detect(b"\xff\xfea\x00"));
+        assert_eq!(Encoding::Utf16Be, Encoding::detect(b"\xfe\xff\x00a"));
+        assert_eq!(
+            Encoding::Utf16Le,
+            Encoding::detect(b"l\x00o\x00g\x00\n\x00")
+        );
+        assert_eq!(
+            Encoding::Utf16Be,
+            Encoding::detect(b"\x00l\x00o\x00g\x00\n")
+        );
+        assert_eq!(
+            Encoding::Windows1252,
+            Encoding::detect(b"caf\xe9 cr\xe8me\n")
+        );
+        assert_eq!(Encoding::Utf8, Encoding::detect("café\n".as_bytes()));
+        // UTF-8 with a stray byte.
+        let content = [
+            "crème brûlée, café".as_bytes(),
+            b"\xff",
+            " au lait\n".as_bytes(),
+        ]
+        .concat();
+        assert_eq!(Encoding::Utf8, Encoding::detect(&content));
+        assert_eq!(Encoding::Utf8, Encoding::detect(b""));
+        // Binary content isn't decoded.
+        assert_eq!(
+            Encoding::Utf8,
+            Encoding::detect(b"\x01\x00\x02\x00\x03\x00")
+        );
+        assert_eq!(
+            Encoding::Utf8,
+            Encoding::detect(b"\x7fELF\x02\x01\xff\xfe\x00\x00")
+        );
+    }
+
+    #[test]
+    fn test_decode() {
+        let utf16 = b"\xff\xfeh\x00\xe9\x00\n\x00=\xd8\x00\xde\n\x00";
+        assert_eq!("hé\n😀\n", Encoding::Utf16Le.decode(utf16));
+        assert_eq!("a\u{fffd}", Encoding::Utf16Be.decode(b"\x00a\x00"));
+        assert_eq!("\u{fffd}", Encoding::Utf16Le.decode(b"\x00\xd8"));
+        assert_eq!("é\u{80}", Encoding::Latin1.decode(b"\xe9\x80"));
+        assert_eq!(
+            "é€\u{81}“",
+            Encoding::Windows1252.decode(b"\xe9\x80\x81\x93")
+        );
+        assert_eq!("x", Encoding::Utf8.decode(b"\xef\xbb\xbfx"));
+        assert_eq!("a\u{fffd}b", Encoding::Utf8.decode(b"a\xe2\x82b"));
+        assert_eq!(Some(Encoding::Windows1252), Encoding::from_name("CP1252"));
+        assert_eq!("latin1", Encoding::Latin1.name());
+    }
+
+    #[test]
+    fn test_offsets() {
+        // `hé\n😀\n`, the emoji is a surrogate pair.
+        let utf16 = b"\xff\xfeh\x00\xe9\x00\n\x00=\xd8\x00\xde\n\x00";
+        let (text, offsets) = Encoding::Utf16Le.decode_with_offsets(utf16);
+        assert_eq!("hé\n😀\n", text);
+        assert_eq!(0, offsets.text_offset(utf16, 0));
+        assert_eq!(4, offsets.text_offset(utf16, 8));
+        // In the middle of the surrogate pair.
+        assert_eq!(4, offsets.text_offset(utf16, 10));
+        assert_eq!(2, offsets.file_offset(utf16, 0));
+        assert_eq!(12, offsets.file_offset(utf16, 8));
+        assert_eq!(14, offsets.file_offset(utf16, text.len()));
+
+        // More than one checkpoint.
+        let latin1: Vec<u8> = b"\xe9\n".repeat(5000);
+        let (text, offsets) = Encoding::Latin1.decode_with_offsets(&latin1);
+        assert_eq!(15000, text.len());
+        assert_eq!(6000, offsets.text_offset(&latin1, 4000));
+        assert_eq!(7001, offsets.file_offset(&latin1, 10502));
+    }
+}
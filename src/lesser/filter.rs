@@ -0,0 +1,223 @@
+use crate::lesser::pattern::Pattern;
+use crate::lesser::reader::{EndIndex, StartIndex};
+use std::cmp;
+
+const NEW_LINE_CHAR: u8 = b'\n';
+
+/// Row number in the file, with its start-end indexes.
+pub(crate) type NumberedRow = (usize, (StartIndex, EndIndex));
+
+/// The rows of the file matching a pattern, or not matching it if the filter is inverted.
+/// Runs of blank rows can also be squeezed into one.
+/// The file is scanned lazily, only as far as the rows requested.
+pub(crate) struct Filter {
+    /// None lets all the rows pass.
+    pattern: Option<Pattern>,
+    invert: bool,
+    /// Only the first of consecutive blank rows passes.
+    squeeze_blank_lines: bool,
+    /// The lines end with CRLF: a row with only `\r` is blank too.
+    crlf: bool,
+    /// The last row scanned is blank and passed the filter, even if it was squeezed.
+    previous_blank: bool,
+    /// Rows passing the filter found so far.
+    rows: Vec<NumberedRow>,
+    /// Where the scan stopped: the start of the next row to check, and its number.
+    next_byte: usize,
+    next_row: usize,
+}
+
+impl Filter {
+    pub(crate) fn new(pattern: Pattern, invert: bool) -> Filter {
+        Filter {
+            pattern: Some(pattern),
+            invert,
+            squeeze_blank_lines: false,
+            crlf: false,
+            previous_blank: false,
+            rows: vec![],
+            next_byte: 0,
+            next_row: 0,
+        }
+    }
+
+    /// A filter letting all the rows pass, to squeeze the blank ones.
+    pub(crate) fn all() -> Filter {
+        Filter {
+            pattern: None,
+            invert: false,
+            squeeze_blank_lines: false,
+            crlf: false,
+            previous_blank: false,
+            rows: vec![],
+            next_byte: 0,
+            next_row: 0,
+        }
+    }
+
+    /// The same filter, squeezing the blank rows or not, for lines ending with CRLF or not.
+    /// The content is scanned again.
+    pub(crate) fn squeezing_blank_lines(self, squeeze_blank_lines: bool, crlf: bool) -> Filter {
+        Filter {
+            squeeze_blank_lines,
+            crlf,
+            previous_blank: false,
+            rows: vec![],
+            next_byte: 0,
+            next_row: 0,
+            ..self
+        }
+    }
+
+    pub(crate) fn has_pattern(&self) -> bool {
+        self.pattern.is_some()
+    }
+
+    pub(crate) fn rows(&self) -> &[NumberedRow] {
+        &self.rows
+    }
+
+    pub(crate) fn is_all_read(&self, content: &[u8]) -> bool {
+        self.next_byte >= content.len()
+    }
+
+    /// Scans the content until `rows` rows passed the filter, or the content ends.
+    pub(crate) fn fetch_rows(&mut self, content: &[u8], rows: usize) {
+        while self.rows.len() < rows && !self.is_all_read(content) {
+            self.scan_row(content);
+        }
+    }
+
+    /// Scans the content until the row containing the byte at `offset`.
+    pub(crate) fn fetch_to_byte(&mut self, content: &[u8], offset: usize) {
+        while self.next_byte <= offset && !self.is_all_read(content) {
+            self.scan_row(content);
+        }
+    }
+
+    fn scan_row(&mut self, content: &[u8]) {
+        let start = self.next_byte;
+        let end = content[start..]
+            .iter()
+            .position(|c| *c == NEW_LINE_CHAR)
+            .map(|i| i + start)
+            .unwrap_or_else(|| content.len());
+        let passes = match &self.pattern {
+            Some(pattern) => pattern.is_match(&content[start..end]) != self.invert,
+            None => true,
+        };
+        // Like less, only the adjacent lines of the file are squeezed.
+        let blank = is_blank(&content[start..end], self.crlf);
+        let squeezed = self.squeeze_blank_lines && self.previous_blank && blank;
+        if passes && !squeezed {
+            self.rows.push((self.next_row, (start, end)));
+        }
+        self.previous_blank = passes && blank;
+        self.next_byte = end + 1;
+        self.next_row += 1;
+    }
+
+    /// Finds the first of the filtered rows after (or before, if backward) `from_row` matching
+    /// `pattern`.
+    pub(crate) fn find_row(
+        &mut self,
+        content: &[u8],
+        pattern: &Pattern,
+        from_row: usize,
+        backward: bool,
+    ) -> Option<usize> {
+        let row_matches =
+            |(_number, (start, end)): &NumberedRow| pattern.is_match(&content[*start..*end]);
+        if backward {
+            let from_row = cmp::min(from_row, self.rows.len());
+            return self.rows[..from_row].iter().rposition(row_matches);
+        }
+        let mut row = from_row + 1;
+        loop {
+            self.fetch_rows(content, row + 1);
+            if row >= self.rows.len() {
+                return None;
+            }
+            if row_matches(&self.rows[row]) {
+                return Some(row);
+            }
+            row += 1;
+        }
+    }
+}
+
+/// Whether the row is empty, or has only the `\r` of CRLF.
+fn is_blank(row: &[u8], crlf: bool) -> bool {
+    row.is_empty() || (crlf && row == b"\r")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lesser::filter::Filter;
+    use crate::lesser::pattern::{CaseMode, Pattern};
+
+    #[test]
+    fn test_filter_rows() {
+        let content = b"GET /a\nPOST /b\nGET /c\n";
+        let mut filter = Filter::new(Pattern::new("GET", CaseMode::Sensitive), false);
+        filter.fetch_rows(content, 10);
+        assert_eq!(&[(0, (0, 6)), (2, (15, 21))], filter.rows());
+
+        let mut filter = Filter::new(Pattern::new("GET", CaseMode::Sensitive), true);
+        filter.fetch_rows(content, 10);
+        assert_eq!(&[(1, (7, 14))], filter.rows());
+    }
+
+    #[test]
+    fn test_squeeze_blank_lines() {
+        // A row with a space isn't blank.
+        let content = b"a\n\n\n \n\nb\n\nc\n";
+        let mut filter = Filter::all().squeezing_blank_lines(true, false);
+        filter.fetch_rows(content, 10);
+        assert_eq!(
+            &[
+                (0, (0, 1)),
+                (1, (2, 2)),
+                (3, (4, 5)),
+                (4, (6, 6)),
+                (5, (7, 8)),
+                (6, (9, 9)),
+                (7, (10, 11))
+            ],
+            filter.rows()
+        );
+        // Only the adjacent lines of the file are squeezed, not the ones around a line hidden
+        // by the filter.
+        let mut filter = Filter::new(Pattern::new("b", CaseMode::Sensitive), true)
+            .squeezing_blank_lines(true, false);
+        filter.fetch_rows(content, 10);
+        assert_eq!(
+            &[
+                (0, (0, 1)),
+                (1, (2, 2)),
+                (3, (4, 5)),
+                (4, (6, 6)),
+                (6, (9, 9)),
+                (7, (10, 11))
+            ],
+            filter.rows()
+        );
+        let mut filter = Filter::all().squeezing_blank_lines(true, true);
+        filter.fetch_rows(b"a\r\n\r\n\r\nb", 10);
+        assert_eq!(&[(0, (0, 2)), (1, (3, 4)), (3, (7, 8))], filter.rows());
+    }
+
+    #[test]
+    fn test_filter_find_row() {
+        let content = b"a 1\nb 1\na 2\nb 2\na 3";
+        let mut filter = Filter::new(Pattern::new("a", CaseMode::Sensitive), false);
+        let pattern = Pattern::new("3", CaseMode::Sensitive);
+        assert_eq!(Some(2), filter.find_row(content, &pattern, 0, false));
+        assert_eq!(Some(2), filter.find_row(content, &pattern, 0, false));
+        assert_eq!(None, filter.find_row(content, &pattern, 2, false));
+        assert_eq!(
+            Some(0),
+            filter.find_row(content, &Pattern::new("1", CaseMode::Sensitive), 2, true)
+        );
+    }
+}
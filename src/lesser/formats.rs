@@ -1,13 +1,70 @@
+use crate::lesser::page::Color;
+use crate::lesser::pattern::CaseMode;
+use crate::lesser::save::Range;
+use std::path::PathBuf;
+
 #[derive(Debug)]
 pub(crate) enum Message {
     ScrollDownPage,
     ScrollDown,
     ScrollUpPage,
     ScrollUp,
+    /// Half page scrolls, optionally setting the number of rows scrolled from now on.
+    ScrollDownHalfPage(Option<u64>),
+    ScrollUpHalfPage(Option<u64>),
+    /// Page scrolls, optionally setting the number of rows scrolled from now on.
+    ScrollDownWindow(Option<u64>),
+    ScrollUpWindow(Option<u64>),
     ScrollLeft,
     ScrollRight,
     ScrollToBeginning,
     ScrollToEnd,
+    /// Jump to the given percentage of the file.
+    GotoPercent(u64),
+    /// Jump to the row containing the given byte offset.
+    GotoByte(u64),
+    /// Save the current position in the given mark.
+    SetMark(char),
+    /// Go back to the position saved in the given mark.
+    GotoMark(char),
+    /// Search a pattern, forward or backward. An empty pattern repeats the last search.
+    Search {
+        pattern: String,
+        backward: bool,
+    },
+    /// Repeat the last search, in the same direction.
+    SearchNext,
+    /// Repeat the last search, in the opposite direction.
+    SearchPrevious,
+    /// Show only the rows matching the pattern (or not matching it, if it starts with `!`).
+    Filter(String),
+    /// Highlight the matches of the pattern, in the color if given.
+    Highlight {
+        pattern: String,
+        color: Option<Color>,
+    },
+    /// Stop highlighting the pattern, or all of them if None.
+    RemoveHighlight(Option<String>),
+    /// Show the content as a hexdump, or as text.
+    Hexdump(bool),
+    /// Write the lines of the range to the file, replacing it only with `overwrite`.
+    Save {
+        range: Range,
+        path: PathBuf,
+        overwrite: bool,
+    },
+    /// Switch between the case mode and the case sensitive search.
+    ToggleCase(CaseMode),
+    /// Mouse click on the given row of the screen (0-based).
+    Click(u16),
+    /// The line being typed in the prompt changed. None if the prompt was closed.
+    Prompt(Option<String>),
     Exit,
+    /// Show the help instead of the file, or go back to the file.
+    Help,
+    /// A worker thread failed: stop, and report the error once the terminal is restored.
+    Error(String),
+    /// Give the terminal back and stop the process, like Ctrl-Z.
+    Suspend,
     Reload,
 }
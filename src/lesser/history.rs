@@ -0,0 +1,309 @@
+use crate::lesser::line_editor::add_to_history;
+use crate::lesser::screen_move_handler::Position;
+use log::debug;
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::fs::{MetadataExt, OpenOptionsExt};
+use std::path::{Path, PathBuf};
+use std::{env, process, str::FromStr};
+
+/// Type of the lines storing the state of a file.
+const FILE_ENTRY: &str = "file";
+/// Type of the lines storing a searched pattern.
+const SEARCH_ENTRY: &str = "search";
+/// How many files are remembered. The least recently read are forgotten first.
+const MAX_FILES: usize = 500;
+
+/// Reading position and marks of a file.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct FileState {
+    pub(crate) position: Position,
+    pub(crate) marks: HashMap<char, Position>,
+}
+
+/// Identifies a file by its canonical path and by its device and inode, so that a new file
+/// created with the same name doesn't inherit the position of the old one.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct FileId {
+    path: PathBuf,
+    dev: u64,
+    ino: u64,
+}
+
+impl FileId {
+    pub(crate) fn of(path: &Path) -> io::Result<FileId> {
+        let path = fs::canonicalize(path)?;
+        let metadata = fs::metadata(&path)?;
+        Ok(FileId {
+            path,
+            dev: metadata.dev(),
+            ino: metadata.ino(),
+        })
+    }
+}
+
+/// State saved across sessions, in a file in the user's data directory.
+/// Every line is an entry, with tab separated fields. The first field is the type of the entry:
+/// `file <path> <dev> <ino> <top_byte> <col_offset> <marks>`
+/// where top_byte is the offset of the first byte of the top row, and marks are a comma
+/// separated list of `<letter>:<top_byte>:<col_offset>`, or
+/// `search <pattern>` for the patterns searched, in any file.
+pub(crate) struct History {
+    path: PathBuf,
+    /// From the least to the most recently read.
+    files: Vec<(FileId, FileState)>,
+    /// From the oldest to the most recent.
+    searches: Vec<String>,
+    /// Files whose state was set since the history was loaded, and patterns searched since, so
+    /// that they are added to the history on disk when saving.
+    changed_files: Vec<FileId>,
+    new_searches: Vec<String>,
+}
+
+impl History {
+    /// `$XDG_DATA_HOME/lesser/history`, or `~/.local/share/lesser/history`.
+    pub(crate) fn default_path() -> Option<PathBuf> {
+        env::var_os("XDG_DATA_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share")))
+            .map(|dir| dir.join("lesser").join("history"))
+    }
+
+    /// Loads the history. A missing file is an empty history, malformed lines are skipped.
+    pub(crate) fn load(path: PathBuf) -> History {
+        let mut history = History {
+            path,
+            files: vec![],
+            searches: vec![],
+            changed_files: vec![],
+            new_searches: vec![],
+        };
+        let file = match File::open(&history.path) {
+            Ok(file) => file,
+            Err(error) => {
+                debug!("History not loaded: {}", error);
+                return history;
+            }
+        };
+        for line in BufReader::new(file).lines() {
+            let line = match line {
+                Ok(line) => line,
+                Err(_) => break,
+            };
+            if let Some(pattern) = line.strip_prefix(&format!("{}\t", SEARCH_ENTRY)) {
+                add_to_history(&mut history.searches, pattern);
+                continue;
+            }
+            match parse_file_entry(&line) {
+                Some(entry) => history.files.push(entry),
+                None => debug!("Skipping history line: {:?}", line),
+            }
+        }
+        history
+    }
+
+    pub(crate) fn file_state(&self, id: &FileId) -> Option<&FileState> {
+        self.files
+            .iter()
+            .find(|(file_id, _state)| file_id == id)
+            .map(|(_file_id, state)| state)
+    }
+
+    /// Stores the state of the file, as the most recently read.
+    pub(crate) fn set_file_state(&mut self, id: FileId, state: FileState) {
+        self.changed_files.retain(|file_id| *file_id != id);
+        self.changed_files.push(id.clone());
+        self.files.retain(|(file_id, _state)| *file_id != id);
+        self.files.push((id, state));
+        let too_many = self.files.len().saturating_sub(MAX_FILES);
+        self.files.drain(..too_many);
+    }
+
+    pub(crate) fn searches(&self) -> &[String] {
+        &self.searches
+    }
+
+    /// Stores the pattern as the most recent search.
+    pub(crate) fn add_search(&mut self, pattern: &str) {
+        add_to_history(&mut self.searches, pattern);
+        add_to_history(&mut self.new_searches, pattern);
+    }
+
+    /// Adds the changes of this session to the history on disk, which other sessions may have
+    /// saved since it was loaded, and writes it.
+    pub(crate) fn save(&self) -> io::Result<()> {
+        let mut merged = History::load(self.path.clone());
+        for id in &self.changed_files {
+            if let Some(state) = self.file_state(id) {
+                merged.set_file_state(id.clone(), state.clone());
+            }
+        }
+        for pattern in &self.new_searches {
+            merged.add_search(pattern);
+        }
+        merged.write()
+    }
+
+    /// Writes the history in a temporary file first, so a crash can't leave it half written.
+    /// The temporary file is of this process, so that two sessions saving at the same time
+    /// don't write in the same one. Like `.lesshst`, only the user can read it: it has the
+    /// paths of the files read and the patterns searched.
+    fn write(&self) -> io::Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let tmp_path = self.path.with_extension(format!("{}.tmp", process::id()));
+        // The mode is only set when creating the file, not on one left by a crash.
+        let _ = fs::remove_file(&tmp_path);
+        let file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(&tmp_path)?;
+        let mut file = io::BufWriter::new(file);
+        for (id, state) in &self.files {
+            if let Some(line) = format_file_entry(id, state) {
+                writeln!(file, "{}", line)?;
+            }
+        }
+        // A pattern can't have a new line, but it can have tabs.
+        for pattern in &self.searches {
+            writeln!(file, "{}\t{}", SEARCH_ENTRY, pattern)?;
+        }
+        file.into_inner()?.sync_all()?;
+        fs::rename(tmp_path, &self.path)
+    }
+}
+
+fn parse_file_entry(line: &str) -> Option<(FileId, FileState)> {
+    let fields: Vec<&str> = line.split('\t').collect();
+    match fields.as_slice() {
+        [FILE_ENTRY, path, dev, ino, top_byte, col_offset, marks] => {
+            let id = FileId {
+                path: PathBuf::from(path),
+                dev: dev.parse().ok()?,
+                ino: ino.parse().ok()?,
+            };
+            let mut state = FileState {
+                position: (top_byte.parse().ok()?, col_offset.parse().ok()?),
+                marks: HashMap::new(),
+            };
+            for mark in marks.split(',').filter(|mark| !mark.is_empty()) {
+                let mut parts = mark.split(':');
+                let letter = char::from_str(parts.next()?).ok()?;
+                let top_byte = parts.next()?.parse().ok()?;
+                let col_offset = parts.next()?.parse().ok()?;
+                state.marks.insert(letter, (top_byte, col_offset));
+            }
+            Some((id, state))
+        }
+        _ => None,
+    }
+}
+
+/// Returns None if the path can't be stored in the history.
+fn format_file_entry(id: &FileId, state: &FileState) -> Option<String> {
+    let path = id
+        .path
+        .to_str()
+        .filter(|path| !path.contains(&['\t', '\n'][..]))?;
+    let mut marks: Vec<String> = state
+        .marks
+        .iter()
+        .map(|(letter, (top_byte, col_offset))| format!("{}:{}:{}", letter, top_byte, col_offset))
+        .collect();
+    marks.sort();
+    Some(format!(
+        "{}\t{}\t{}\t{}\t{}\t{}\t{}",
+        FILE_ENTRY,
+        path,
+        id.dev,
+        id.ino,
+        state.position.0,
+        state.position.1,
+        marks.join(",")
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lesser::history::{FileId, FileState, History};
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+
+    #[test]
+    fn test_save_and_load() {
+        let tempdir = tempdir::TempDir::new("lesser").unwrap();
+        let read_file = tempdir.path().join("read");
+        fs::write(&read_file, "content").unwrap();
+        let id = FileId::of(&read_file).unwrap();
+        let history_path = tempdir.path().join("data").join("history");
+
+        let mut history = History::load(history_path.clone());
+        assert_eq!(None, history.file_state(&id));
+        let mut state = FileState {
+            position: (120, 80),
+            ..Default::default()
+        };
+        state.marks.insert('a', (10, 0));
+        state.marks.insert('b', (40, 160));
+        history.set_file_state(id.clone(), state.clone());
+        history.add_search("request\tid");
+        history.add_search("error");
+        history.add_search("request\tid");
+        history.save().unwrap();
+
+        // Only the user can read the history.
+        let mode = fs::metadata(&history_path).unwrap().permissions().mode();
+        assert_eq!(0o600, mode & 0o777);
+
+        let history = History::load(history_path);
+        assert_eq!(Some(&state), history.file_state(&id));
+        assert_eq!(["error", "request\tid"], history.searches());
+    }
+
+    #[test]
+    fn test_save_merges_sessions() {
+        let tempdir = tempdir::TempDir::new("lesser").unwrap();
+        let history_path = tempdir.path().join("history");
+        let mut ids = vec![];
+        for name in &["first", "second", "third"] {
+            let read_file = tempdir.path().join(name);
+            fs::write(&read_file, "content").unwrap();
+            ids.push(FileId::of(&read_file).unwrap());
+        }
+        let state = |top_byte| FileState {
+            position: (top_byte, 0),
+            ..Default::default()
+        };
+        let mut history = History::load(history_path.clone());
+        history.set_file_state(ids[0].clone(), state(1));
+        history.save().unwrap();
+
+        // Two sessions open at the same time: each one keeps what the other saved.
+        let mut first = History::load(history_path.clone());
+        let mut second = History::load(history_path.clone());
+        first.set_file_state(ids[1].clone(), state(2));
+        first.add_search("first");
+        second.set_file_state(ids[2].clone(), state(3));
+        second.add_search("second");
+        first.save().unwrap();
+        second.save().unwrap();
+
+        let history = History::load(history_path);
+        assert_eq!(Some(&state(1)), history.file_state(&ids[0]));
+        assert_eq!(Some(&state(2)), history.file_state(&ids[1]));
+        assert_eq!(Some(&state(3)), history.file_state(&ids[2]));
+        assert_eq!(["first", "second"], history.searches());
+        // No temporary file is left.
+        let names: Vec<_> = fs::read_dir(tempdir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .filter(|name| name.to_string_lossy().starts_with("history"))
+            .collect();
+        assert_eq!(vec!["history"], names);
+    }
+}
@@ -0,0 +1,427 @@
+use crate::lesser::bindings::{Action, KeyBindings, Lookup};
+use crate::lesser::formats::Message;
+use crate::lesser::line_editor::{add_to_history, LineEditor};
+use crate::lesser::page::Color;
+use crate::lesser::save::{complete_path, parse_save, Range};
+use crate::lesser::screen_move_handler::{LAST_POSITION_MARK, MOUSE_MARK};
+use std::path::PathBuf;
+use termion::event::Key;
+
+/// Turns the keys typed into messages, following the key bindings.
+pub(crate) struct KeyHandler {
+    bindings: KeyBindings,
+    /// Numeric argument typed before a command, e.g. the 50 in `50p`.
+    count: Option<u64>,
+    /// Line being typed in the prompt, after the character of the prompt (`:`, `/`, `?`, `&` or
+    /// `s`).
+    command: Option<(char, LineEditor)>,
+    /// Patterns searched, from the oldest to the most recent.
+    searches: Vec<String>,
+    /// SetMark or GotoMark, waiting for the name of the mark.
+    mark_command: Option<Action>,
+    /// Whether the help is shown instead of the file.
+    help_shown: bool,
+    /// Keys typed so far of a longer sequence, e.g. the first `g` of `gg`.
+    pending: Vec<Key>,
+    /// Waiting for the answer to the question of showing binary content as text or hex.
+    asking_hexdump: bool,
+    /// Save to an existing file, waiting for the confirmation to overwrite it.
+    confirming_save: Option<(Range, PathBuf)>,
+}
+
+impl KeyHandler {
+    pub(crate) fn new(bindings: KeyBindings, searches: Vec<String>) -> KeyHandler {
+        KeyHandler {
+            bindings,
+            count: None,
+            command: None,
+            searches,
+            mark_command: None,
+            help_shown: false,
+            pending: vec![],
+            asking_hexdump: false,
+            confirming_save: None,
+        }
+    }
+
+    /// The next key answers whether to show the content as a hexdump: `h` or `x` for hex, `q`
+    /// to quit, anything else for text.
+    pub(crate) fn ask_hexdump(&mut self) {
+        self.asking_hexdump = true;
+    }
+
+    pub(crate) fn handle(&mut self, key: Key) -> Vec<Message> {
+        let mut messages = vec![];
+        self.handle_key(key, &mut messages);
+        messages
+    }
+
+    fn handle_key(&mut self, key: Key, messages: &mut Vec<Message>) {
+        if self.asking_hexdump {
+            self.asking_hexdump = false;
+            messages.push(Message::Prompt(None));
+            match key {
+                Key::Char('h') | Key::Char('x') => messages.push(Message::Hexdump(true)),
+                Key::Char('q') => messages.push(Message::Exit),
+                _ => {}
+            }
+            return;
+        }
+
+        if let Some((range, path)) = self.confirming_save.take() {
+            messages.push(Message::Prompt(None));
+            if key == Key::Char('y') {
+                messages.push(Message::Save {
+                    range,
+                    path,
+                    overwrite: true,
+                });
+            }
+            return;
+        }
+
+        if let Some((prompt_key, editor)) = self.command.take() {
+            self.edit_prompt(prompt_key, editor, key, messages);
+            return;
+        }
+
+        if let Some(mark_command) = self.mark_command.take() {
+            let message = match key {
+                Key::Char(letter)
+                    if letter.is_ascii_alphabetic() && mark_command == Action::SetMark =>
+                {
+                    Some(Message::SetMark(letter))
+                }
+                Key::Char(letter)
+                    if mark_command == Action::GotoMark
+                        && (letter.is_ascii_alphabetic()
+                            || letter == LAST_POSITION_MARK
+                            || letter == MOUSE_MARK) =>
+                {
+                    Some(Message::GotoMark(letter))
+                }
+                _ => None,
+            };
+            messages.extend(message);
+            return;
+        }
+
+        // Esc cancels the count, even when it starts a bound sequence.
+        if key == Key::Esc {
+            self.count = None;
+        }
+
+        if let (Key::Char(digit @ '0'..='9'), true) = (key, self.pending.is_empty()) {
+            let digit = digit.to_digit(10).unwrap_or(0) as u64;
+            self.count = Some(
+                self.count
+                    .unwrap_or(0)
+                    .saturating_mul(10)
+                    .saturating_add(digit),
+            );
+            return;
+        }
+
+        self.pending.push(key);
+        match self.bindings.lookup(&self.pending) {
+            Lookup::Prefix => {}
+            Lookup::Action(action) => {
+                self.pending.clear();
+                self.run(action, messages);
+            }
+            Lookup::None => {
+                // Runs the longest bound start of the sequence, and handles the keys after it
+                // again. Without any, the first key does nothing.
+                let keys = std::mem::take(&mut self.pending);
+                let bound = (1..keys.len())
+                    .rev()
+                    .find_map(|len| self.bindings.action(&keys[..len]).map(|a| (len, a)));
+                let rest = match bound {
+                    Some((len, action)) => {
+                        self.run(action, messages);
+                        &keys[len..]
+                    }
+                    None => {
+                        self.count = None;
+                        &keys[1..]
+                    }
+                };
+                for key in rest {
+                    self.handle_key(*key, messages);
+                }
+            }
+        }
+    }
+
+    fn run(&mut self, action: Action, messages: &mut Vec<Message>) {
+        let count = self.count.take();
+        let message = match action {
+            // Quitting goes back to the file from the help.
+            Action::Exit if self.help_shown => {
+                self.help_shown = false;
+                Some(Message::Help)
+            }
+            Action::Help => {
+                self.help_shown = !self.help_shown;
+                Some(Message::Help)
+            }
+            Action::SetMark | Action::GotoMark => {
+                self.mark_command = Some(action);
+                None
+            }
+            action => match action.prompt() {
+                Some(prompt_key) => {
+                    // The searches forward and backward share the history.
+                    let history = match prompt_key {
+                        '/' | '?' => self.searches.clone(),
+                        _ => vec![],
+                    };
+                    let editor = LineEditor::new(history);
+                    let prompt = prompt_line(prompt_key, &editor);
+                    self.command = Some((prompt_key, editor));
+                    Some(Message::Prompt(Some(prompt)))
+                }
+                None => action.message(count),
+            },
+        };
+        messages.extend(message);
+    }
+
+    fn edit_prompt(
+        &mut self,
+        prompt_key: char,
+        mut editor: LineEditor,
+        key: Key,
+        messages: &mut Vec<Message>,
+    ) {
+        match key {
+            Key::Char('\n') => {
+                messages.push(Message::Prompt(None));
+                let line = editor.line();
+                let message = match prompt_key {
+                    '/' | '?' => {
+                        add_to_history(&mut self.searches, &line);
+                        Some(Message::Search {
+                            pattern: line,
+                            backward: prompt_key == '?',
+                        })
+                    }
+                    '&' => Some(Message::Filter(line)),
+                    's' => match parse_save(&line) {
+                        Some((range, path)) if path.exists() => {
+                            let question =
+                                format!("{} exists, overwrite it? (y/n)", path.display());
+                            messages.push(Message::Prompt(Some(question)));
+                            self.confirming_save = Some((range, path));
+                            None
+                        }
+                        Some((range, path)) => Some(Message::Save {
+                            range,
+                            path,
+                            overwrite: false,
+                        }),
+                        None => None,
+                    },
+                    _ => parse_command(&line),
+                };
+                messages.extend(message);
+            }
+            Key::Esc | Key::Ctrl('c') => self.cancel_prompt(messages),
+            Key::Backspace if editor.is_empty() => self.cancel_prompt(messages),
+            key => {
+                let edited = match key {
+                    Key::Char('\t') if prompt_key == 's' => {
+                        editor.insert(&complete_path(&editor.before_cursor()));
+                        true
+                    }
+                    key => editor.edit(key),
+                };
+                if edited {
+                    messages.push(Message::Prompt(Some(prompt_line(prompt_key, &editor))));
+                }
+                self.command = Some((prompt_key, editor));
+            }
+        }
+    }
+
+    /// Closes the prompt without running the command, nor the count typed before it.
+    fn cancel_prompt(&mut self, messages: &mut Vec<Message>) {
+        self.count = None;
+        messages.push(Message::Prompt(None));
+    }
+}
+
+/// The prompt line: the character of the prompt, or what it asks, before the line typed.
+fn prompt_line(prompt_key: char, editor: &LineEditor) -> String {
+    match prompt_key {
+        's' => format!("Save to: {}", editor.display()),
+        _ => format!("{}{}", prompt_key, editor.display()),
+    }
+}
+
+fn parse_command(command: &str) -> Option<Message> {
+    let command = command.trim();
+    // `hi [color] pattern`, `unhi [pattern]`, `hex` and `text`.
+    let (name, argument) = match command.find(' ') {
+        Some(space) => (&command[..space], command[space..].trim_start()),
+        None => (command, ""),
+    };
+    match name {
+        "hi" if !argument.is_empty() => {
+            let (color, pattern) = match argument.split_once(' ') {
+                Some((color, pattern)) if Color::from_name(color).is_some() => {
+                    (Color::from_name(color), pattern.trim_start())
+                }
+                _ => (None, argument),
+            };
+            return Some(Message::Highlight {
+                pattern: pattern.to_string(),
+                color,
+            });
+        }
+        "hex" | "text" if argument.is_empty() => return Some(Message::Hexdump(name == "hex")),
+        "unhi" => {
+            let pattern = Some(argument.to_string()).filter(|pattern| !pattern.is_empty());
+            return Some(Message::RemoveHighlight(pattern));
+        }
+        _ => {}
+    }
+    if let Some(offset) = command.strip_prefix('b') {
+        return offset.trim().parse().ok().map(Message::GotoByte);
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lesser::bindings::KeyBindings;
+    use crate::lesser::formats::Message;
+    use crate::lesser::key_handler::{parse_command, KeyHandler};
+    use crate::lesser::page::Color;
+    use termion::event::Key;
+
+    fn type_keys(handler: &mut KeyHandler, keys: &str) -> Vec<Message> {
+        keys.chars()
+            .flat_map(|c| handler.handle(Key::Char(c)))
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_command() {
+        assert!(matches!(
+            parse_command("b 1048576"),
+            Some(Message::GotoByte(1048576))
+        ));
+        assert!(matches!(parse_command("b12"), Some(Message::GotoByte(12))));
+        assert!(parse_command("b twelve").is_none());
+        assert!(parse_command("x").is_none());
+        assert!(matches!(
+            parse_command("hi red  ERROR 500"),
+            Some(Message::Highlight { pattern, color: Some(Color::Red) }) if pattern == "ERROR 500"
+        ));
+        assert!(matches!(
+            parse_command("hi 4bf92f35"),
+            Some(Message::Highlight { pattern, color: None }) if pattern == "4bf92f35"
+        ));
+        assert!(matches!(parse_command("hex"), Some(Message::Hexdump(true))));
+        assert!(matches!(
+            parse_command("unhi"),
+            Some(Message::RemoveHighlight(None))
+        ));
+    }
+
+    #[test]
+    fn test_key_sequences() {
+        let mut handler = KeyHandler::new(KeyBindings::new(), vec![]);
+        assert!(matches!(
+            type_keys(&mut handler, "50p")[..],
+            [Message::GotoPercent(50)]
+        ));
+        assert!(matches!(type_keys(&mut handler, "h")[..], [Message::Help]));
+        // q leaves the help first.
+        assert!(matches!(
+            type_keys(&mut handler, "qq")[..],
+            [Message::Help, Message::Exit]
+        ));
+        // A cancelled prompt drops the count typed before.
+        type_keys(&mut handler, "5/");
+        assert!(matches!(
+            handler.handle(Key::Esc)[..],
+            [Message::Prompt(None)]
+        ));
+        assert!(matches!(
+            type_keys(&mut handler, "d")[..],
+            [Message::ScrollDownHalfPage(None)]
+        ));
+        assert!(matches!(
+            type_keys(&mut handler, "5d")[..],
+            [Message::ScrollDownHalfPage(Some(5))]
+        ));
+
+        let mut bindings = KeyBindings::new();
+        bindings
+            .apply(
+                "[keys]\n\"gg\" = \"beginning\"\n\":n\" = \"end\"\n\"<Esc>d\" = \"half-page-down\"\n",
+            )
+            .unwrap();
+        let mut handler = KeyHandler::new(bindings, vec![]);
+        assert!(matches!(
+            type_keys(&mut handler, "gg")[..],
+            [Message::ScrollToBeginning]
+        ));
+        assert!(matches!(
+            type_keys(&mut handler, ":n")[..],
+            [Message::ScrollToEnd]
+        ));
+        // Esc drops the count, in a sequence too.
+        type_keys(&mut handler, "5");
+        handler.handle(Key::Esc);
+        assert!(matches!(
+            type_keys(&mut handler, "d")[..],
+            [Message::ScrollDownHalfPage(None)]
+        ));
+        // `:` alone still opens the prompt, the next key is typed in it.
+        let messages = type_keys(&mut handler, ":b 5\n");
+        assert!(matches!(messages.last(), Some(Message::GotoByte(5))));
+        // `g` followed by another key is still `g`.
+        assert!(matches!(
+            type_keys(&mut handler, "gj")[..],
+            [Message::ScrollToBeginning, Message::ScrollDown]
+        ));
+
+        // The up arrow recalls the last search, in both directions.
+        type_keys(&mut handler, "/foo\n");
+        handler.handle(Key::Char('?'));
+        handler.handle(Key::Up);
+        assert!(matches!(
+            &handler.handle(Key::Char('\n'))[..],
+            [Message::Prompt(None), Message::Search { pattern, backward: true }] if pattern == "foo"
+        ));
+
+        // Saving to an existing file asks first.
+        let dir = tempdir::TempDir::new("lesser").unwrap();
+        let path = dir.path().join("saved");
+        let messages = type_keys(&mut handler, &format!("s{}\n", path.display()));
+        assert!(matches!(
+            messages.last(),
+            Some(Message::Save {
+                overwrite: false,
+                ..
+            })
+        ));
+        std::fs::write(&path, "").unwrap();
+        type_keys(&mut handler, &format!("s{}\n", path.display()));
+        let messages = type_keys(&mut handler, "y");
+        assert!(matches!(
+            &messages[..],
+            [
+                Message::Prompt(None),
+                Message::Save {
+                    overwrite: true,
+                    ..
+                }
+            ]
+        ));
+        assert!(matches!(&messages[1], Message::Save { path: saved, .. } if *saved == path));
+    }
+}
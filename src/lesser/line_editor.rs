@@ -0,0 +1,198 @@
+use termion::event::Key;
+use termion::style::{Invert, NoInvert};
+
+/// How many searches are remembered.
+pub(crate) const MAX_HISTORY: usize = 100;
+
+/// Line typed in the prompt, with a cursor and the lines entered before.
+pub(crate) struct LineEditor {
+    line: Vec<char>,
+    /// Position of the cursor in line, from 0 to its length.
+    cursor: usize,
+    /// Lines entered before, from the oldest to the most recent.
+    history: Vec<String>,
+    /// Index in history of the line shown, None while typing a new line.
+    browsing: Option<usize>,
+    /// The new line, kept while browsing the history. Only the lines starting with it are shown.
+    typed: Vec<char>,
+}
+
+impl LineEditor {
+    pub(crate) fn new(history: Vec<String>) -> LineEditor {
+        LineEditor {
+            line: vec![],
+            cursor: 0,
+            history,
+            browsing: None,
+            typed: vec![],
+        }
+    }
+
+    pub(crate) fn line(&self) -> String {
+        self.line.iter().collect()
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.line.is_empty()
+    }
+
+    pub(crate) fn before_cursor(&self) -> String {
+        self.line[..self.cursor].iter().collect()
+    }
+
+    /// Inserts the text at the cursor, as if it was typed.
+    pub(crate) fn insert(&mut self, text: &str) {
+        for c in text.chars() {
+            self.edit(Key::Char(c));
+        }
+    }
+
+    /// Edits the line as in a shell. Returns false for the keys that don't edit it.
+    pub(crate) fn edit(&mut self, key: Key) -> bool {
+        match key {
+            Key::Char(c) => {
+                self.line.insert(self.cursor, c);
+                self.cursor += 1;
+            }
+            Key::Backspace if self.cursor > 0 => {
+                self.cursor -= 1;
+                self.line.remove(self.cursor);
+            }
+            Key::Delete | Key::Ctrl('d') if self.cursor < self.line.len() => {
+                self.line.remove(self.cursor);
+            }
+            Key::Left | Key::Ctrl('b') => self.cursor = self.cursor.saturating_sub(1),
+            Key::Right | Key::Ctrl('f') => self.cursor = (self.cursor + 1).min(self.line.len()),
+            Key::Home | Key::Ctrl('a') => self.cursor = 0,
+            Key::End | Key::Ctrl('e') => self.cursor = self.line.len(),
+            Key::Ctrl('w') => {
+                // The word before the cursor, and the spaces after it.
+                let before = &self.line[..self.cursor];
+                let word_end = before.iter().rposition(|c| !c.is_whitespace());
+                let start = word_end
+                    .and_then(|end| before[..end].iter().rposition(|c| c.is_whitespace()))
+                    .map_or(0, |space| space + 1);
+                self.line.drain(start..self.cursor);
+                self.cursor = start;
+            }
+            Key::Ctrl('u') => {
+                self.line.drain(..self.cursor);
+                self.cursor = 0;
+            }
+            Key::Ctrl('k') => self.line.truncate(self.cursor),
+            Key::Up | Key::Ctrl('p') => self.browse(true),
+            Key::Down | Key::Ctrl('n') => self.browse(false),
+            Key::Backspace | Key::Delete | Key::Ctrl('d') => {}
+            _ => return false,
+        }
+        if !matches!(key, Key::Up | Key::Down | Key::Ctrl('p') | Key::Ctrl('n')) {
+            // Editing a line of the history starts a new line.
+            self.browsing = None;
+        }
+        true
+    }
+
+    /// Shows the previous or next line of the history starting with the typed text.
+    fn browse(&mut self, older: bool) {
+        if self.browsing.is_none() {
+            self.typed = self.line.clone();
+        }
+        let typed: String = self.typed.iter().collect();
+        let matching = |i: &usize| self.history[*i].starts_with(&typed);
+        let found = match (older, self.browsing) {
+            (true, None) => (0..self.history.len()).rev().find(matching),
+            (true, Some(index)) => (0..index).rev().find(matching).or(Some(index)),
+            (false, Some(index)) => (index + 1..self.history.len()).find(matching),
+            (false, None) => return,
+        };
+        self.line = match found {
+            Some(index) => self.history[index].chars().collect(),
+            // Past the most recent line, back to the typed one.
+            None => self.typed.clone(),
+        };
+        self.browsing = found;
+        self.cursor = self.line.len();
+    }
+
+    /// The line with the cursor shown in inverted colors.
+    pub(crate) fn display(&self) -> String {
+        let before: String = self.line[..self.cursor].iter().collect();
+        let at = self.line.get(self.cursor).copied().unwrap_or(' ');
+        let after: String = self.line.iter().skip(self.cursor + 1).collect();
+        format!("{}{}{}{}{}", before, Invert, at, NoInvert, after)
+    }
+}
+
+/// Adds the line as the most recent of the history, removing it from the older ones.
+pub(crate) fn add_to_history(history: &mut Vec<String>, line: &str) {
+    if line.is_empty() {
+        return;
+    }
+    history.retain(|old| old != line);
+    history.push(line.to_string());
+    let too_many = history.len().saturating_sub(MAX_HISTORY);
+    history.drain(..too_many);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lesser::line_editor::LineEditor;
+    use termion::event::Key;
+
+    fn type_keys(editor: &mut LineEditor, keys: &[Key]) -> String {
+        for key in keys {
+            editor.edit(*key);
+        }
+        editor.line()
+    }
+
+    #[test]
+    fn test_edit() {
+        let mut editor = LineEditor::new(vec![]);
+        let keys: Vec<Key> = "grep foo bar".chars().map(Key::Char).collect();
+        assert_eq!("grep foo bar", type_keys(&mut editor, &keys));
+        assert_eq!("grep foo ", type_keys(&mut editor, &[Key::Ctrl('w')]));
+        assert_eq!(
+            "grep fo",
+            type_keys(&mut editor, &[Key::Left, Key::Backspace, Key::Ctrl('k')])
+        );
+        let keys = [
+            Key::Left,
+            Key::Left,
+            Key::Left,
+            Key::Ctrl('u'),
+            Key::Char('x'),
+        ];
+        assert_eq!("x fo", type_keys(&mut editor, &keys));
+        assert_eq!(
+            "x fo",
+            type_keys(&mut editor, &[Key::End, Key::Right, Key::Delete])
+        );
+        assert!(!editor.edit(Key::Esc));
+    }
+
+    #[test]
+    fn test_history() {
+        let history = vec![
+            "error".to_string(),
+            "req-1".to_string(),
+            "req-2".to_string(),
+        ];
+        let mut editor = LineEditor::new(history);
+        assert_eq!("req-2", type_keys(&mut editor, &[Key::Up]));
+        assert_eq!(
+            "error",
+            type_keys(&mut editor, &[Key::Up, Key::Up, Key::Up])
+        );
+        assert_eq!(
+            "",
+            type_keys(&mut editor, &[Key::Down, Key::Down, Key::Down])
+        );
+        // Only the lines starting with the typed text.
+        assert_eq!(
+            "req-1",
+            type_keys(&mut editor, &[Key::Char('r'), Key::Up, Key::Up, Key::Up])
+        );
+        assert_eq!("r", type_keys(&mut editor, &[Key::Down, Key::Down]));
+    }
+}
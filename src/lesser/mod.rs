@@ -1,38 +1,110 @@
+use crate::lesser::bindings::KeyBindings;
 use crate::lesser::formats::Message;
+use crate::lesser::history::{FileId, History};
+use crate::lesser::key_handler::KeyHandler;
+use crate::lesser::page::Page;
 use crate::lesser::reader::PagedReader;
+use crate::lesser::render::{is_binary, is_crlf};
+use crate::lesser::screen_buffer::ScreenBuffer;
 use crate::lesser::screen_move_handler::ScreenMoveHandler;
+use crate::lesser::terminal::Terminal;
 use crossbeam_channel::Sender;
-use io::{stdin, stdout, ErrorKind, Stdout, Write};
-use log::debug;
+use io::{stdin, ErrorKind, Write};
+use libc::SIGTSTP;
+use log::{debug, warn};
 use memmap::{Mmap, MmapMut};
-use signal_hook::{iterator::Signals, SIGINT, SIGWINCH};
+use signal_hook::{iterator::Signals, SIGCONT, SIGHUP, SIGINT, SIGSTOP, SIGTERM, SIGWINCH};
+use std::cmp;
 use std::fs::{File, OpenOptions};
+use std::panic::{self, AssertUnwindSafe};
 use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 use std::thread::JoinHandle;
 use std::{fs, io, thread};
-use termion::cursor;
-use termion::event::Key;
+use termion::event::{Event, MouseButton, MouseEvent};
 use termion::input::TermRead;
-use termion::raw::{IntoRawMode, RawTerminal};
-use termion::screen;
+use termion::raw::IntoRawMode;
 use termion::{is_tty, terminal_size};
+use unicode_width::UnicodeWidthStr;
 
+mod bindings;
+mod config;
+mod encoding;
+mod filter;
 mod formats;
+mod history;
+mod key_handler;
+mod line_editor;
+mod page;
+mod pattern;
 mod reader;
+mod render;
+mod save;
+mod screen_buffer;
 mod screen_move_handler;
+mod terminal;
 
-pub fn run(filename: Option<PathBuf>) -> io::Result<()> {
-    let screen = screen::AlternateScreen::from(stdout()).into_raw_mode()?;
-    let mut screen = cursor::HideCursor::from(screen);
+pub use config::Config;
+pub use encoding::Encoding;
+pub use pattern::CaseMode;
+pub use screen_move_handler::JumpTarget;
+
+pub struct Options {
+    /// Restore the reading position and the marks of the file from the previous session, and
+    /// save them on exit.
+    pub use_history: bool,
+    /// Show the line numbers.
+    pub line_numbers: bool,
+    /// Enable mouse reporting, to scroll with the mouse wheel.
+    pub mouse: bool,
+    /// Print the content and exit if it fits in one screen, like `less -F`.
+    pub quit_if_one_screen: bool,
+    /// Use the alternate screen, so that the page disappears on exit. `less -X` disables it.
+    pub alternate_screen: bool,
+    /// How the case is matched by the searches and the filters.
+    pub case: CaseMode,
+    /// Show consecutive blank lines as a single one, like `less -s`.
+    pub squeeze_blank_lines: bool,
+    /// Show the control characters and the invalid UTF-8 as hex, e.g. `<1B>`, instead of `^[`
+    /// and `�`.
+    pub hex_bytes: bool,
+    /// Distance between the tab stops. If None, 8 columns.
+    pub tab_width: Option<usize>,
+    /// Show the colors set by the SGR escape sequences of the content, like `less -R`.
+    pub raw_colors: bool,
+    /// Where the targets of the searches and the jumps are shown, like `less -j`. If None,
+    /// the searches show them on top and the other jumps in the middle.
+    pub jump_target: Option<JumpTarget>,
+    /// Show the content as a hexdump, or as text. If None, lesser asks for binary content.
+    pub hexdump: Option<bool>,
+    /// Encoding of the content. If None, it's guessed from the content.
+    pub encoding: Option<Encoding>,
+}
+
+/// Asked when opening binary content, answered by the key handler.
+const HEXDUMP_QUESTION: &str = "Binary content: show it as (t)ext or (h)ex?";
+
+pub fn run(filename: Option<PathBuf>, options: Options) -> io::Result<()> {
+    let key_bindings = match KeyBindings::default_path() {
+        Some(path) => KeyBindings::load(&path)?,
+        None => KeyBindings::new(),
+    };
+    let help_page = key_bindings.help_text();
 
     let (sender, receiver) = crossbeam_channel::bounded(100);
+    let file_id = filename
+        .as_ref()
+        .filter(|_| options.use_history)
+        .and_then(|filename| FileId::of(filename).ok());
+    // None for an empty content, that can't be mapped.
     let mmap = if let Some(filename) = filename {
         let file_size = std::fs::metadata(&filename)?.len();
         if file_size > 0 {
             let file = File::open(filename)?;
-            unsafe { Mmap::map(&file).expect("failed to map the file") }
+            Some(unsafe { Mmap::map(&file)? })
         } else {
-            MmapMut::map_anon(1).expect("Anon mmap").make_read_only()?
+            None
         }
     } else if !is_tty(&stdin()) {
         read_all_from_pipe()?
@@ -45,53 +117,282 @@ pub fn run(filename: Option<PathBuf>) -> io::Result<()> {
         return Err(error);
     };
 
-    let paged_reader = PagedReader::new(mmap);
+    let encoding = options
+        .encoding
+        .unwrap_or_else(|| Encoding::detect(mmap.as_deref().unwrap_or_default()));
+    // The content is decoded to UTF-8 in memory, so that the rows are found by their new line.
+    let mut paged_reader = match mmap {
+        Some(mmap) if encoding != Encoding::Utf8 => PagedReader::decoded(mmap, encoding)?,
+        Some(mmap) => PagedReader::new(mmap),
+        None => PagedReader::empty()?,
+    };
+    let text = paged_reader.text();
+    if options.quit_if_one_screen {
+        let (cols, rows) = page_size();
+        if fits_in_page(text, rows, cols) {
+            let mut out = io::stdout();
+            out.write_all(text)?;
+            return out.flush();
+        }
+    }
+    // Without a choice, lesser asks how to show binary content. Choosing an encoding is
+    // choosing the text.
+    let ask_hexdump = options.hexdump.is_none() && options.encoding.is_none() && is_binary(text);
+    let crlf = is_crlf(text);
+
+    // Without mouse reporting, the terminal keeps handling the mouse for selecting text.
+    let mut screen = Terminal::new(options.mouse, options.alternate_screen)?;
+    install_panic_hook();
+
+    paged_reader.set_line_numbers(options.line_numbers);
+    paged_reader.set_hex_bytes(options.hex_bytes);
+    if let Some(tab_width) = options.tab_width {
+        paged_reader.set_tab_width(tab_width);
+    }
+    paged_reader.set_raw_colors(options.raw_colors);
+    paged_reader.set_crlf(crlf);
+    paged_reader.set_squeeze_blank_lines(options.squeeze_blank_lines);
     let mut screen_move_handler: ScreenMoveHandler = ScreenMoveHandler::new(paged_reader);
-    spawn_key_pressed_handler(sender.clone());
-    spawn_signal_handler(sender)?;
-    let (cols, rows) = terminal_size().unwrap_or((80, 80));
+    screen_move_handler.set_case(options.case);
+    if let Some(jump_target) = options.jump_target {
+        screen_move_handler.set_jump_target(jump_target);
+    }
+    // The searches are remembered for the pipes too.
+    let mut history = History::default_path()
+        .filter(|_| options.use_history)
+        .map(History::load);
+    if let (Some(history), Some(file_id)) = (&history, &file_id) {
+        if let Some(state) = history.file_state(file_id) {
+            screen_move_handler.restore_file_state(state.clone());
+        }
+    }
+    let searches = history
+        .as_ref()
+        .map(|history| history.searches().to_vec())
+        .unwrap_or_default();
+    let mut key_handler = KeyHandler::new(key_bindings, searches);
+    if ask_hexdump {
+        key_handler.ask_hexdump();
+    }
+    spawn_key_pressed_handler(sender.clone(), key_handler);
+    // Set while a Reload is waiting in the channel, to redraw once for many resize events.
+    let reload_pending = Arc::new(AtomicBool::new(false));
+    spawn_signal_handler(sender, reload_pending.clone())?;
+    let (cols, rows) = page_size();
 
+    let mut screen_buffer = ScreenBuffer::new();
+    if options.hexdump == Some(true) {
+        screen_move_handler.set_hexdump(true, rows, cols)?;
+    }
     let initial_screen = screen_move_handler.initial_screen(rows, cols)?;
-    write_screen(&mut screen, initial_screen)?;
+    write_screen(&mut screen, &mut screen_buffer, initial_screen, rows, cols)?;
+    // Shown in the prompt line when there is no prompt, with what is unusual in the content.
+    let mut markers = vec![];
+    if encoding != Encoding::Utf8 {
+        markers.push(encoding.name().to_uppercase());
+    }
+    if crlf {
+        markers.push("CRLF".to_string());
+    }
+    let status = if markers.is_empty() {
+        ":".to_string()
+    } else {
+        format!("[{}] :", markers.join(" "))
+    };
+    // Content of the prompt line, if the user is typing a command.
+    let mut prompt: Option<String> = Some(HEXDUMP_QUESTION.to_string()).filter(|_| ask_hexdump);
+    write_prompt(&mut screen, rows, &prompt, &status)?;
+    let mut help: Option<ScreenMoveHandler> = None;
 
     for message in receiver {
-        let (cols, rows) = terminal_size().unwrap_or((80, 80));
+        if let Message::Reload = message {
+            // Resizes from now on send a new Reload, the size is read after this one.
+            reload_pending.store(false, Ordering::SeqCst);
+        }
+        let (cols, rows) = page_size();
+        if let Message::Help = message {
+            help = match help {
+                Some(_) => None,
+                None => Some(ScreenMoveHandler::new(help_reader(&help_page)?)),
+            };
+        }
+        // While the help is shown, the keys move in the help.
+        let handler = help.as_mut().unwrap_or(&mut screen_move_handler);
         let page = match message {
-            Message::ScrollUpPage => screen_move_handler.move_up_page(rows, cols)?,
-            Message::ScrollDownPage => screen_move_handler.move_down_page(rows, cols)?,
-            Message::ScrollLeft => screen_move_handler.move_left(rows, cols)?,
-            Message::ScrollRight => screen_move_handler.move_right(rows, cols)?,
-            Message::ScrollUp => screen_move_handler.move_up(rows, cols)?,
-            Message::ScrollDown => screen_move_handler.move_down(rows, cols)?,
-            Message::ScrollToBeginning => screen_move_handler.move_to_top(rows, cols)?,
-            Message::ScrollToEnd => screen_move_handler.move_to_end(rows, cols)?,
-            Message::Reload => screen_move_handler.reload(rows, cols)?,
+            Message::ScrollUpPage => handler.move_up_page(rows, cols)?,
+            Message::ScrollDownPage => handler.move_down_page(rows, cols)?,
+            Message::ScrollLeft => handler.move_left(rows, cols)?,
+            Message::ScrollRight => handler.move_right(rows, cols)?,
+            Message::ScrollUp => handler.move_up(rows, cols)?,
+            Message::ScrollDownHalfPage(size) => handler.move_down_half_page(size, rows, cols)?,
+            Message::ScrollUpHalfPage(size) => handler.move_up_half_page(size, rows, cols)?,
+            Message::ScrollDownWindow(size) => handler.move_down_window(size, rows, cols)?,
+            Message::ScrollUpWindow(size) => handler.move_up_window(size, rows, cols)?,
+            Message::ScrollDown => handler.move_down(rows, cols)?,
+            Message::ScrollToBeginning => handler.move_to_top(rows, cols)?,
+            Message::ScrollToEnd => handler.move_to_end(rows, cols)?,
+            Message::GotoPercent(percent) => handler.goto_percent(percent, rows, cols)?,
+            Message::GotoByte(offset) => handler.goto_byte(offset, rows, cols)?,
+            Message::Search { pattern, backward } => {
+                if let Some(history) = &mut history {
+                    history.add_search(&pattern);
+                }
+                handler.search(&pattern, backward, rows, cols)?
+            }
+            Message::SearchNext => handler.search_next(false, rows, cols)?,
+            Message::SearchPrevious => handler.search_next(true, rows, cols)?,
+            Message::Filter(pattern) => handler.filter(&pattern, rows, cols)?,
+            Message::Highlight { pattern, color } => {
+                handler.highlight(&pattern, color, rows, cols)?
+            }
+            Message::RemoveHighlight(pattern) => {
+                handler.remove_highlight(pattern.as_deref(), rows, cols)?
+            }
+            Message::Hexdump(hexdump) => handler.set_hexdump(hexdump, rows, cols)?,
+            Message::ToggleCase(case) => {
+                let message = match handler.toggle_case(case) {
+                    CaseMode::Sensitive => "Case is significant in searches",
+                    CaseMode::Smart => "Ignore case in searches without uppercase letters",
+                    CaseMode::Insensitive => "Ignore case in searches",
+                };
+                // The highlights can change.
+                let page = handler.reload(rows, cols)?;
+                write_screen(&mut screen, &mut screen_buffer, page, rows, cols)?;
+                write_prompt(&mut screen, rows, &Some(message.to_string()), &status)?;
+                continue;
+            }
+            Message::Save {
+                range,
+                path,
+                overwrite,
+            } => {
+                // The file is saved even from the help.
+                let message = match screen_move_handler.save(range, &path, overwrite) {
+                    Ok(bytes) => format!("Saved {} bytes to {}", bytes, path.display()),
+                    Err(error) => format!("Can't save to {}: {}", path.display(), error),
+                };
+                write_prompt(&mut screen, rows, &Some(message), &status)?;
+                continue;
+            }
+            Message::SetMark(letter) => {
+                handler.set_mark(letter);
+                continue;
+            }
+            Message::GotoMark(letter) => handler.goto_mark(letter, rows, cols)?,
+            Message::Click(row) if row < rows => handler.click(row, rows, cols)?,
+            // A click on the prompt line.
+            Message::Click(_row) => continue,
+            Message::Reload => {
+                // The terminal could have changed its content as well.
+                screen_buffer.invalidate();
+                handler.reload(rows, cols)?
+            }
+            Message::Prompt(new_prompt) => {
+                prompt = new_prompt;
+                write_prompt(&mut screen, rows, &prompt, &status)?;
+                continue;
+            }
+            Message::Suspend => {
+                screen.leave()?;
+                // Stop like Ctrl-Z would do, the process group continues on SIGCONT.
+                // SIGSTOP is used because SIGTSTP is handled by the signal handler.
+                unsafe {
+                    libc::kill(0, SIGSTOP);
+                }
+                screen.enter()?;
+                // SIGCONT triggers a reload, that will draw the whole screen again.
+                screen_buffer.invalidate();
+                continue;
+            }
+            Message::Error(error) => return Err(io::Error::other(error)),
+            Message::Help => handler.reload(rows, cols)?,
             Message::Exit => break,
         };
 
-        write_screen(&mut screen, page)?;
+        if write_screen(&mut screen, &mut screen_buffer, page, rows, cols)? {
+            write_prompt(&mut screen, rows, &prompt, &status)?;
+        }
+    }
+
+    if let Some(history) = &mut history {
+        if let Some(file_id) = file_id {
+            history.set_file_state(file_id, screen_move_handler.file_state());
+        }
+        if let Err(error) = history.save() {
+            warn!("Failed to save the history: {}", error);
+        }
     }
     Ok(())
 }
-fn signal_handler_thread_main(sender: Sender<Message>, signals: Signals) {
+fn signal_handler_thread_main(
+    sender: Sender<Message>,
+    signals: Signals,
+    reload_pending: Arc<AtomicBool>,
+) {
     for sig in signals.forever() {
         let msg = match sig {
-            signal_hook::SIGWINCH => Message::Reload,
+            SIGWINCH | SIGCONT => {
+                if reload_pending.swap(true, Ordering::SeqCst) {
+                    // The pending one will use the new size.
+                    continue;
+                }
+                Message::Reload
+            }
+            SIGTSTP => Message::Suspend,
             _ => Message::Exit,
         };
-        sender.send(msg).unwrap();
         debug!("Received signal {:?}", sig);
+        if sender.send(msg).is_err() {
+            // The main loop is gone.
+            break;
+        }
     }
 }
-fn spawn_signal_handler(sender: Sender<Message>) -> io::Result<JoinHandle<()>> {
-    let signals = Signals::new(&[SIGWINCH, SIGINT])?;
+fn spawn_signal_handler(
+    sender: Sender<Message>,
+    reload_pending: Arc<AtomicBool>,
+) -> io::Result<JoinHandle<()>> {
+    let signals = Signals::new([SIGWINCH, SIGINT, SIGTERM, SIGHUP, SIGTSTP, SIGCONT])?;
     Ok(thread::spawn(move || {
-        signal_handler_thread_main(sender, signals);
+        signal_handler_thread_main(sender, signals, reload_pending);
     }))
 }
 
+/// Whether the content, printed as is, takes at most `rows` rows. The long lines are wrapped
+/// by the terminal.
+fn fits_in_page(content: &[u8], rows: u16, cols: u16) -> bool {
+    let content = content.strip_suffix(b"\n").unwrap_or(content);
+    let mut used_rows = 0;
+    for line in content.split(|b| *b == b'\n') {
+        let width = String::from_utf8_lossy(line).width();
+        used_rows += cmp::max(1, width.div_ceil(cmp::max(cols as usize, 1)));
+        if used_rows > rows as usize {
+            return false;
+        }
+    }
+    true
+}
+
+/// The help page, in memory.
+fn help_reader(help: &str) -> io::Result<PagedReader> {
+    match map_in_memory(help.as_bytes())? {
+        Some(mmap) => Ok(PagedReader::new(mmap)),
+        None => PagedReader::empty(),
+    }
+}
+
+/// The bytes copied in an anonymous map, None if they are empty.
+fn map_in_memory(bytes: &[u8]) -> io::Result<Option<Mmap>> {
+    if bytes.is_empty() {
+        return Ok(None);
+    }
+    let mut mmap = MmapMut::map_anon(bytes.len())?;
+    mmap.copy_from_slice(bytes);
+    Ok(Some(mmap.make_read_only()?))
+}
+
 /// TODO: reading everything from the pipe is easy but not smart / efficient.
-fn read_all_from_pipe() -> io::Result<Mmap> {
+fn read_all_from_pipe() -> io::Result<Option<Mmap>> {
     //let (sender, receiver) = crossbeam_channel::unbounded();
     let tempdir = tempdir::TempDir::new("lesser")?;
     let path: PathBuf = tempdir.path().join("map_mut");
@@ -99,64 +400,171 @@ fn read_all_from_pipe() -> io::Result<Mmap> {
         .read(true)
         .write(true)
         .create(true)
-        .open(&path)
-        .expect("Create file");
+        .truncate(true)
+        .open(&path)?;
     let mut stdin = stdin();
-    io::copy(&mut stdin, &mut file).expect("copy pipe input");
-    Ok(unsafe { Mmap::map(&file).expect("mmap") })
+    if io::copy(&mut stdin, &mut file)? == 0 {
+        return Ok(None);
+    }
+    Ok(Some(unsafe { Mmap::map(&file)? }))
 }
-fn key_pressed_handler_thread_main(sender: Sender<Message>) {
+/// Reads the keys until the main loop is gone. Failures, panics included, are sent to the
+/// main loop, which gives the terminal back before reporting them.
+fn key_pressed_handler_thread_main(sender: Sender<Message>, key_handler: KeyHandler) {
+    let result = panic::catch_unwind(AssertUnwindSafe(|| read_keys(&sender, key_handler)));
+    let error = match result {
+        Ok(Ok(())) => return,
+        Ok(Err(error)) => format!("Failed to read the keys: {}", error),
+        // The panic hook has already printed the panic message.
+        Err(_) => "The key handler panicked".to_string(),
+    };
+    let _ = sender.send(Message::Error(error));
+}
+
+fn read_keys(sender: &Sender<Message>, mut key_handler: KeyHandler) -> io::Result<()> {
     let tty = fs::OpenOptions::new()
         .read(true)
         .write(true)
-        .open("/dev/tty")
-        .expect("Open tty");
+        .open("/dev/tty")?;
 
     // Can use the tty_input for keys while also reading stdin for data.
-    let tty_input = tty
-        .try_clone()
-        .expect("Try clone")
-        .into_raw_mode()
-        .expect("Into raw mode");
-
-    for c in tty_input.try_clone().unwrap().keys() {
-        let message = match c.expect("read keys") {
-            Key::Char('q') => Some(Message::Exit),
-            Key::PageUp | Key::Char('b') => Some(Message::ScrollUpPage),
-            Key::PageDown | Key::Char(' ') | Key::Char('f') => Some(Message::ScrollDownPage),
-            Key::Left => Some(Message::ScrollLeft),
-            Key::Down | Key::Char('\n') | Key::Char('e') | Key::Char('j') => {
-                Some(Message::ScrollDown)
+    let tty_input = tty.try_clone()?.into_raw_mode()?;
+
+    for event in tty_input.try_clone()?.events() {
+        let key = match event {
+            Ok(Event::Key(key)) => key,
+            // termion fails with Other on the sequences it can't parse.
+            Err(error) if error.kind() == ErrorKind::Other => {
+                debug!("Skipped an event: {}", error);
+                continue;
+            }
+            Err(error) => return Err(error),
+            Ok(event) => {
+                if let Some(message) = mouse_message(&event) {
+                    send(sender, message)?;
+                }
+                continue;
             }
-            Key::Up | Key::Char('y') | Key::Char('k') => Some(Message::ScrollUp),
-            Key::Right => Some(Message::ScrollRight),
-            Key::Char('g') | Key::Home => Some(Message::ScrollToBeginning),
-            Key::Char('G') | Key::End => Some(Message::ScrollToEnd),
-            // Not-implemented keys do nothing
-            _ => None,
         };
-        if let Some(message) = message {
-            sender.send(message).unwrap();
+        for message in key_handler.handle(key) {
+            send(sender, message)?;
+        }
+    }
+    Ok(())
+}
+
+fn send(sender: &Sender<Message>, message: Message) -> io::Result<()> {
+    sender
+        .send(message)
+        .map_err(|_| io::Error::new(ErrorKind::BrokenPipe, "the main loop is gone"))
+}
+
+/// Scroll messages for the mouse wheel, and the click. termion doesn't parse the horizontal
+/// wheel and the shift + wheel, they arrive as unsupported SGR (1006) sequences:
+/// `ESC [ < button ; column ; row M`.
+fn mouse_message(event: &Event) -> Option<Message> {
+    match event {
+        Event::Mouse(MouseEvent::Press(MouseButton::WheelUp, _x, _y)) => Some(Message::ScrollUp),
+        Event::Mouse(MouseEvent::Press(MouseButton::WheelDown, _x, _y)) => {
+            Some(Message::ScrollDown)
+        }
+        Event::Mouse(MouseEvent::Press(MouseButton::Left, _x, y)) => {
+            Some(Message::Click(y.saturating_sub(1)))
+        }
+        Event::Unsupported(sequence) => {
+            let sequence = std::str::from_utf8(sequence).ok()?;
+            let sequence = sequence.strip_prefix("\x1b[<")?.strip_suffix('M')?;
+            let button: u16 = sequence.split(';').next()?.parse().ok()?;
+            match button {
+                // Wheel left, or shift + wheel up.
+                66 | 68 => Some(Message::ScrollLeft),
+                // Wheel right, or shift + wheel down.
+                67 | 69 => Some(Message::ScrollRight),
+                _ => None,
+            }
         }
+        _ => None,
     }
 }
 
-fn spawn_key_pressed_handler(sender: Sender<Message>) {
-    thread::spawn(move || key_pressed_handler_thread_main(sender));
+fn spawn_key_pressed_handler(sender: Sender<Message>, key_handler: KeyHandler) {
+    thread::spawn(move || key_pressed_handler_thread_main(sender, key_handler));
+}
+
+/// Gives the terminal back before the panic message is printed, otherwise it would be lost
+/// with the alternate screen.
+fn install_panic_hook() {
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        terminal::restore();
+        default_hook(info);
+    }));
+}
+
+/// Size of the area used by the page: the last row of the terminal is kept for the prompt.
+fn page_size() -> (u16, u16) {
+    let (cols, rows) = terminal_size().unwrap_or((80, 80));
+    (cols, rows.saturating_sub(1))
+}
+
+/// Writes the prompt in the line below the page. If there is no prompt, shows the status: `:` like
+/// less, after the encoding and the line ending if they are not the usual ones.
+fn write_prompt(
+    screen: &mut dyn Write,
+    rows: u16,
+    prompt: &Option<String>,
+    status: &str,
+) -> io::Result<()> {
+    write!(screen, "{}", termion::cursor::Goto(1, rows + 1))?;
+    write!(screen, "{}", termion::clear::CurrentLine)?;
+    write!(screen, "{}", prompt.as_deref().unwrap_or(status))?;
+    screen.flush()
 }
 
 /// If page is None, then we made a read which didn't return anything.
+/// Returns true if the screen was cleared, and the prompt needs to be written again.
 fn write_screen(
-    screen: &mut RawTerminal<screen::AlternateScreen<Stdout>>,
-    page: Option<String>,
-) -> io::Result<()> {
-    match page {
-        Some(page) => {
-            write!(screen, "{}", termion::clear::All)?;
-            write!(screen, "{}", termion::cursor::Goto(1, 1))?;
-            write!(screen, "{}", page)?
+    screen: &mut dyn Write,
+    screen_buffer: &mut ScreenBuffer,
+    page: Option<Page>,
+    rows: u16,
+    cols: u16,
+) -> io::Result<bool> {
+    let redrawn = match page {
+        Some(page) => screen_buffer.draw(screen, &page, rows, cols)?,
+        None => {
+            write!(screen, "\x07")?;
+            false
         }
-        None => write!(screen, "\x07")?,
     };
-    screen.flush()
+    screen.flush()?;
+    Ok(redrawn)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lesser::formats::Message;
+    use crate::lesser::mouse_message;
+    use termion::event::{Event, MouseButton, MouseEvent};
+
+    #[test]
+    fn test_mouse_message() {
+        let wheel_down = Event::Mouse(MouseEvent::Press(MouseButton::WheelDown, 1, 1));
+        assert!(matches!(
+            mouse_message(&wheel_down),
+            Some(Message::ScrollDown)
+        ));
+        let click = Event::Mouse(MouseEvent::Press(MouseButton::Left, 4, 3));
+        assert!(matches!(mouse_message(&click), Some(Message::Click(2))));
+        let wheel_right = Event::Unsupported(b"\x1b[<67;10;5M".to_vec());
+        assert!(matches!(
+            mouse_message(&wheel_right),
+            Some(Message::ScrollRight)
+        ));
+        let shift_wheel_up = Event::Unsupported(b"\x1b[<68;10;5M".to_vec());
+        assert!(matches!(
+            mouse_message(&shift_wheel_up),
+            Some(Message::ScrollLeft)
+        ));
+    }
 }
@@ -0,0 +1,281 @@
+use termion::{color, style};
+
+/// Separator between the rows of a page.
+pub(crate) const ROW_SEPARATOR: &str = "\n\r";
+
+/// Background colors of the highlighted patterns, in the order they are used.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Color {
+    Yellow,
+    Red,
+    Green,
+    Cyan,
+    Magenta,
+    Blue,
+}
+
+pub(crate) const COLORS: &[(Color, &str)] = &[
+    (Color::Yellow, "yellow"),
+    (Color::Red, "red"),
+    (Color::Green, "green"),
+    (Color::Cyan, "cyan"),
+    (Color::Magenta, "magenta"),
+    (Color::Blue, "blue"),
+];
+
+impl Color {
+    pub(crate) fn from_name(name: &str) -> Option<Color> {
+        COLORS
+            .iter()
+            .find(|(_color, color_name)| name.eq_ignore_ascii_case(color_name))
+            .map(|(color, _name)| *color)
+    }
+
+    /// The escape sequence writing the following text in this color.
+    pub(crate) fn escape(self) -> String {
+        let background = match self {
+            Color::Yellow => color::Bg(color::Yellow).to_string(),
+            Color::Red => color::Bg(color::Red).to_string(),
+            Color::Green => color::Bg(color::Green).to_string(),
+            Color::Cyan => color::Bg(color::Cyan).to_string(),
+            Color::Magenta => color::Bg(color::Magenta).to_string(),
+            Color::Blue => color::Bg(color::Blue).to_string(),
+        };
+        format!("{}{}", color::Fg(color::Black), background)
+    }
+}
+
+/// A color of the content, set by its escape sequences when they are shown with `-R`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum RawColor {
+    /// One of the 256 colors of the terminal, the first 16 are the named ones.
+    Indexed(u8),
+    Rgb(u8, u8, u8),
+}
+
+impl RawColor {
+    /// The escape sequence writing the following text in this color, as the foreground or the
+    /// background.
+    fn escape(self, background: bool) -> String {
+        let (normal, bright, extended) = if background {
+            (40, 100, 48)
+        } else {
+            (30, 90, 38)
+        };
+        match self {
+            RawColor::Indexed(index @ 0..=7) => format!("\x1b[{}m", normal + index as u16),
+            RawColor::Indexed(index @ 8..=15) => format!("\x1b[{}m", bright + index as u16 - 8),
+            RawColor::Indexed(index) => format!("\x1b[{};5;{}m", extended, index),
+            RawColor::Rgb(r, g, b) => format!("\x1b[{};2;{};{};{}m", extended, r, g, b),
+        }
+    }
+}
+
+/// How the text is drawn.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) struct Style {
+    /// Background color of a highlight.
+    pub(crate) color: Option<Color>,
+    /// Colors of the content.
+    pub(crate) foreground: Option<RawColor>,
+    pub(crate) background: Option<RawColor>,
+    pub(crate) bold: bool,
+    pub(crate) underline: bool,
+    pub(crate) reverse: bool,
+}
+
+impl Style {
+    /// This style drawn over `under`: the colors replace the ones below, the attributes add up.
+    /// A highlight hides the colors of the content, so that it's always readable.
+    pub(crate) fn over(self, under: Style) -> Style {
+        let color = self.color.or(under.color);
+        Style {
+            color,
+            foreground: self.foreground.or(under.foreground),
+            background: self.background.or(under.background),
+            bold: self.bold || under.bold,
+            underline: self.underline || under.underline,
+            reverse: color.is_none() && (self.reverse || under.reverse),
+        }
+    }
+
+    /// The colors drawn: the one of the highlight, or the ones of the content.
+    fn colors(self) -> (Option<Color>, Option<RawColor>, Option<RawColor>) {
+        match self.color {
+            Some(color) => (Some(color), None, None),
+            None => (None, self.foreground, self.background),
+        }
+    }
+
+    /// The escape sequences changing the style of the following text from `from` to this one.
+    pub(crate) fn escape_from(self, from: Style) -> String {
+        let mut escape = String::new();
+        if self.colors() != from.colors() {
+            match self.colors() {
+                (Some(color), _, _) => escape.push_str(&color.escape()),
+                (None, foreground, background) => {
+                    escape.push_str(&match foreground {
+                        Some(foreground) => foreground.escape(false),
+                        None => color::Fg(color::Reset).to_string(),
+                    });
+                    escape.push_str(&match background {
+                        Some(background) => background.escape(true),
+                        None => color::Bg(color::Reset).to_string(),
+                    });
+                }
+            }
+        }
+        if self.bold != from.bold {
+            // Normal intensity, `style::NoBold` is a double underline on some terminals.
+            escape.push_str(if self.bold { "\x1b[1m" } else { "\x1b[22m" });
+        }
+        if self.underline != from.underline {
+            let underline = if self.underline {
+                style::Underline.to_string()
+            } else {
+                style::NoUnderline.to_string()
+            };
+            escape.push_str(&underline);
+        }
+        if self.reverse != from.reverse {
+            escape.push_str(if self.reverse { "\x1b[7m" } else { "\x1b[27m" });
+        }
+        escape
+    }
+}
+
+impl From<Color> for Style {
+    fn from(color: Color) -> Style {
+        Style {
+            color: Some(color),
+            ..Style::default()
+        }
+    }
+}
+
+/// Part of the text of a page, from `start` to `end` (in bytes), drawn with a style.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Span {
+    pub(crate) start: usize,
+    pub(crate) end: usize,
+    pub(crate) style: Style,
+}
+
+/// Text to show in the terminal, with the rows separated by `ROW_SEPARATOR`.
+/// Spans can overlap, the later ones are drawn over the earlier ones.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Page {
+    pub(crate) text: String,
+    pub(crate) spans: Vec<Span>,
+}
+
+impl Page {
+    /// The rows of the page, as cells with their style.
+    pub(crate) fn cells(&self) -> Vec<Vec<(char, Style)>> {
+        let mut styles = vec![Style::default(); self.text.len()];
+        for span in &self.spans {
+            for style in &mut styles[span.start..span.end] {
+                *style = span.style.over(*style);
+            }
+        }
+        let mut rows = vec![];
+        let mut start = 0;
+        for row in self.text.split(ROW_SEPARATOR) {
+            rows.push(
+                row.char_indices()
+                    .map(|(i, c)| (c, styles[start + i]))
+                    .collect(),
+            );
+            start += row.len() + ROW_SEPARATOR.len();
+        }
+        rows
+    }
+}
+
+impl From<&str> for Page {
+    fn from(text: &str) -> Page {
+        Page {
+            text: text.to_string(),
+            spans: vec![],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lesser::page::{Color, Page, RawColor, Span, Style};
+
+    #[test]
+    fn test_overlapping_spans() {
+        let bold = Style {
+            bold: true,
+            ..Style::default()
+        };
+        let page = Page {
+            text: "abcd\n\ref".to_string(),
+            spans: vec![
+                Span {
+                    start: 0,
+                    end: 2,
+                    style: bold,
+                },
+                Span {
+                    start: 1,
+                    end: 4,
+                    style: Color::Yellow.into(),
+                },
+                Span {
+                    start: 2,
+                    end: 3,
+                    style: Color::Red.into(),
+                },
+                Span {
+                    start: 7,
+                    end: 8,
+                    style: Color::Blue.into(),
+                },
+            ],
+        };
+        let bold_yellow = Style {
+            color: Some(Color::Yellow),
+            bold: true,
+            ..Style::default()
+        };
+        assert_eq!(
+            vec![
+                vec![
+                    ('a', bold),
+                    ('b', bold_yellow),
+                    ('c', Color::Red.into()),
+                    ('d', Color::Yellow.into())
+                ],
+                vec![('e', Style::default()), ('f', Color::Blue.into())]
+            ],
+            page.cells()
+        );
+    }
+
+    #[test]
+    fn test_escape_raw_colors() {
+        let raw = Style {
+            foreground: Some(RawColor::Indexed(9)),
+            background: Some(RawColor::Rgb(0, 0, 128)),
+            reverse: true,
+            ..Style::default()
+        };
+        assert_eq!(
+            "\x1b[91m\x1b[48;2;0;0;128m\x1b[7m",
+            raw.escape_from(Style::default())
+        );
+        // A highlight hides the colors of the content.
+        let highlighted = Style::from(Color::Yellow).over(raw);
+        assert_eq!(
+            Color::Yellow.escape() + "\x1b[27m",
+            highlighted.escape_from(raw)
+        );
+        assert_eq!(
+            "\x1b[39m\x1b[49m",
+            Style::default().escape_from(highlighted)
+        );
+    }
+}
@@ -0,0 +1,156 @@
+/// How the case of the letters is matched by the searches and the filters.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CaseMode {
+    Sensitive,
+    /// Ignore the case, unless the pattern has uppercase letters (`-i`).
+    Smart,
+    /// Always ignore the case (`-I`).
+    Insensitive,
+}
+
+/// A pattern searched in the rows of the file. It's matched on the raw bytes, so it works on
+/// files that are not valid UTF-8.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Pattern {
+    bytes: Vec<u8>,
+    /// The case folded pattern, if the case is ignored.
+    folded: Option<Vec<char>>,
+}
+
+impl Pattern {
+    pub(crate) fn new(pattern: &str, case: CaseMode) -> Pattern {
+        let ignore_case = match case {
+            CaseMode::Sensitive => false,
+            CaseMode::Smart => !pattern.chars().any(char::is_uppercase),
+            CaseMode::Insensitive => true,
+        };
+        Pattern {
+            bytes: pattern.as_bytes().to_vec(),
+            folded: Some(pattern.chars().flat_map(fold).collect()).filter(|_| ignore_case),
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    /// Position of the first match in haystack.
+    pub(crate) fn find(&self, haystack: &[u8]) -> Option<usize> {
+        self.find_match(haystack).map(|(start, _end)| start)
+    }
+
+    /// Start and end of the first match in haystack.
+    pub(crate) fn find_match(&self, haystack: &[u8]) -> Option<(usize, usize)> {
+        if self.is_empty() {
+            return Some((0, 0));
+        }
+        match &self.folded {
+            Some(folded) => (0..haystack.len())
+                .find_map(|at| folded_match_end(folded, haystack, at).map(|end| (at, end))),
+            None => haystack
+                .windows(self.bytes.len())
+                .position(|window| window == self.bytes.as_slice())
+                .map(|start| (start, start + self.bytes.len())),
+        }
+    }
+
+    /// Upper bound of the length in bytes of a match.
+    pub(crate) fn max_match_len(&self) -> usize {
+        match &self.folded {
+            // Every character matches at least a folded one, and has at most 4 bytes.
+            Some(folded) => folded.len() * 4,
+            None => self.bytes.len(),
+        }
+    }
+
+    /// Position of the last match in haystack.
+    pub(crate) fn rfind(&self, haystack: &[u8]) -> Option<usize> {
+        if self.is_empty() {
+            return Some(haystack.len());
+        }
+        match &self.folded {
+            Some(folded) => (0..haystack.len())
+                .rev()
+                .find(|at| folded_match_end(folded, haystack, *at).is_some()),
+            None => haystack
+                .windows(self.bytes.len())
+                .rposition(|window| window == self.bytes.as_slice()),
+        }
+    }
+
+    pub(crate) fn is_match(&self, row: &[u8]) -> bool {
+        self.find(row).is_some()
+    }
+}
+
+/// Case folding of a character, e.g. `ß` is `ss` and both `σ` and `ς` are `σ`.
+fn fold(c: char) -> impl Iterator<Item = char> {
+    c.to_uppercase().flat_map(char::to_lowercase)
+}
+
+/// If the UTF-8 characters starting at `at` fold to `folded`, the end of the match.
+/// Invalid UTF-8 never matches.
+fn folded_match_end(folded: &[char], haystack: &[u8], mut at: usize) -> Option<usize> {
+    let mut matched = 0;
+    while matched < folded.len() {
+        let (c, len) = decode_char(&haystack[at..])?;
+        at += len;
+        for c in fold(c) {
+            if folded.get(matched) != Some(&c) {
+                return None;
+            }
+            matched += 1;
+        }
+    }
+    Some(at)
+}
+
+/// The UTF-8 character at the start of bytes, and its length.
+fn decode_char(bytes: &[u8]) -> Option<(char, usize)> {
+    let len = match *bytes.first()? {
+        b if b < 0x80 => 1,
+        b if b >= 0xF0 => 4,
+        b if b >= 0xE0 => 3,
+        b if b >= 0xC0 => 2,
+        // A continuation byte.
+        _ => return None,
+    };
+    let c = std::str::from_utf8(bytes.get(..len)?)
+        .ok()?
+        .chars()
+        .next()?;
+    Some((c, len))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lesser::pattern::{CaseMode, Pattern};
+
+    #[test]
+    fn test_ignore_case() {
+        let content = "Straße ΣΟΦΟΣ\n".as_bytes();
+        assert_eq!(
+            None,
+            Pattern::new("strasse", CaseMode::Sensitive).find(content)
+        );
+        assert_eq!(
+            Some(0),
+            Pattern::new("STRASSE", CaseMode::Insensitive).find(content)
+        );
+        assert_eq!(
+            Some(8),
+            Pattern::new("σοφος", CaseMode::Smart).find(content)
+        );
+        assert_eq!(
+            Some(8),
+            Pattern::new("σοφος", CaseMode::Smart).rfind(content)
+        );
+        // Uppercase letters make the smart case sensitive.
+        assert_eq!(None, Pattern::new("Σοφος", CaseMode::Smart).find(content));
+        assert!(!Pattern::new("a", CaseMode::Insensitive).is_match(b"\xff\xfe"));
+        assert_eq!(
+            Some((0, 7)),
+            Pattern::new("strasse", CaseMode::Smart).find_match(content)
+        );
+    }
+}
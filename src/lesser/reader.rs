@@ -1,52 +1,143 @@
-use memmap::Mmap;
+use crate::lesser::encoding::{Encoding, Offsets};
+use crate::lesser::filter::{Filter, NumberedRow};
+use crate::lesser::page::{Color, Page, Span, ROW_SEPARATOR};
+use crate::lesser::pattern::Pattern;
+use crate::lesser::render::{hexdump_row, push_styled, Glyphs, RenderOptions};
+use memmap::{Mmap, MmapMut};
+use std::collections::VecDeque;
 use std::{cmp, io};
 
-type StartIndex = usize;
-type EndIndex = usize;
+pub(crate) type StartIndex = usize;
+pub(crate) type EndIndex = usize;
+
+const NEW_LINE_CHAR: u8 = b'\n';
+/// Width of the line numbers column, including the space separating it from the row.
+const LINE_NUMBER_WIDTH: u16 = 8;
 
 pub struct PagedReader {
     /// Start-end row indexes. A row is delimited by an EOL char.
     /// This vector referes to the file, so it's independent from the screen-size.
-    rows_indexes: Vec<(StartIndex, EndIndex)>,
+    /// It's a window over the rows of the file starting at `first_indexed_row`, so that jumping
+    /// far away (e.g. to a byte offset) doesn't require to index all the rows in between.
+    rows_indexes: VecDeque<(StartIndex, EndIndex)>,
+    /// Row number of the first element of rows_indexes.
+    first_indexed_row: usize,
+    /// If set, only the rows passing the filter are shown. Row offsets then refer to the
+    /// filtered rows, while the line numbers shown are still the ones in the file.
+    filter: Option<Filter>,
+    /// Show a run of blank rows as a single one. It's done by the filter, set even without a
+    /// pattern.
+    squeeze_blank_lines: bool,
+    /// Show the line number at the beginning of every row.
+    line_numbers: bool,
+    /// How the rows are shown: the control characters, the tabs and the colors.
+    render: RenderOptions,
+    /// The lines end with CRLF: the `\r` at the end of the rows is not shown.
+    crlf: bool,
+    /// If set, the content is shown as a hexdump with this many bytes per row. The rows are
+    /// then found by their offset, without looking for the new lines.
+    hexdump: Option<usize>,
+    /// Patterns highlighted, each with its color. The later ones are drawn over the others.
+    highlights: Vec<(Pattern, Color)>,
+    /// The text shown, in UTF-8.
     mmap: Mmap,
+    /// Length of the text, that can be shorter than mmap: an empty text is mapped with one byte,
+    /// as an empty map is not allowed.
+    len: usize,
+    /// The content of the file, if the text is decoded from another encoding, with the offsets
+    /// to go from one to the other. The byte offsets taken and returned by the reader are always
+    /// the ones in the file, and the hexdump shows its bytes.
+    file: Option<(Mmap, Offsets)>,
 }
 
 impl PagedReader {
     pub fn new(mmap: Mmap) -> PagedReader {
         PagedReader {
-            rows_indexes: vec![],
+            rows_indexes: VecDeque::new(),
+            first_indexed_row: 0,
+            filter: None,
+            squeeze_blank_lines: false,
+            line_numbers: false,
+            render: RenderOptions::default(),
+            crlf: false,
+            hexdump: None,
+            highlights: vec![],
+            len: mmap.len(),
             mmap,
+            file: None,
         }
     }
 
+    /// A reader showing the content decoded from `encoding` to UTF-8.
+    pub fn decoded(mmap: Mmap, encoding: Encoding) -> io::Result<PagedReader> {
+        let (text, offsets) = encoding.decode_with_offsets(&mmap);
+        let mut paged_reader = if text.is_empty() {
+            PagedReader::empty()?
+        } else {
+            let mut text_mmap = MmapMut::map_anon(text.len())?;
+            text_mmap.copy_from_slice(text.as_bytes());
+            PagedReader::new(text_mmap.make_read_only()?)
+        };
+        paged_reader.file = Some((mmap, offsets));
+        Ok(paged_reader)
+    }
+
+    /// A reader without content, e.g. for an empty file.
+    pub fn empty() -> io::Result<PagedReader> {
+        let mut paged_reader = PagedReader::new(MmapMut::map_anon(1)?.make_read_only()?);
+        paged_reader.len = 0;
+        Ok(paged_reader)
+    }
+
     /// rows_to_read = term height
     /// columns_to_read = term width
-    /// Returns a page. Will start reading from row_offset / column offset and will read
-    /// rows_to_read rows, and columns_to_read columns.
+    /// Returns a page. Will start reading from row_offset / column offset (the first row and
+    /// column shown) and will read rows_to_read rows, and columns_to_read columns.
     pub fn read_file_paged(
         &mut self,
         row_offset: u64,
         column_offset: u64,
         rows_to_read: u16,
         columns_to_read: u16,
-    ) -> std::io::Result<(String, usize, usize)> {
-        let indexes = self.get_rows_indexes(rows_to_read, row_offset)?;
+    ) -> std::io::Result<(Page, usize, usize)> {
+        let indexes = self.get_numbered_rows(rows_to_read, row_offset)?;
         let indexes_len = indexes.len();
-        let mut res = String::new();
+        let content_columns = if self.line_numbers {
+            columns_to_read.saturating_sub(LINE_NUMBER_WIDTH)
+        } else {
+            columns_to_read
+        };
+        let mut res = Page::default();
         let mut has_text = false;
-        for (i, (start_row, end_row)) in indexes.into_iter().enumerate() {
-            let current_column = start_row + column_offset as usize;
-            let start = cmp::min(current_column, end_row);
-            let end = cmp::min(end_row, current_column + columns_to_read as usize);
-
-            let row = &self.mmap[start..end];
-            let as_string = String::from_utf8_lossy(row);
-
-            has_text = has_text || !as_string.is_empty();
-
-            res.push_str(as_string.as_ref());
+        for (i, (row_number, (start_row, end_row))) in indexes.into_iter().enumerate() {
+            if let Some(row_len) = self.hexdump {
+                let row = hexdump_row(start_row, &self.content()[start_row..end_row], row_len);
+                let shown: String = row
+                    .chars()
+                    .skip(column_offset as usize)
+                    .take(columns_to_read as usize)
+                    .collect();
+                has_text = has_text || !shown.is_empty();
+                res.text.push_str(&shown);
+                if i < indexes_len - 1 {
+                    res.text.push_str(ROW_SEPARATOR);
+                }
+                continue;
+            }
+            if self.line_numbers {
+                let width = LINE_NUMBER_WIDTH as usize - 1;
+                res.text
+                    .push_str(&format!("{:>width$} ", row_number + 1, width = width));
+            }
+            let mut row = &self.mmap[start_row..end_row];
+            if self.crlf {
+                // A `\r` elsewhere is shown as `^M`.
+                row = row.strip_suffix(b"\r").unwrap_or(row);
+            }
+            let shown = self.push_row(&mut res, row, column_offset as usize, content_columns);
+            has_text = has_text || shown;
             if i < indexes_len - 1 {
-                res.push_str("\n\r");
+                res.text.push_str(ROW_SEPARATOR);
             }
         }
         // If horizontal scrolling hasn't returned any char, then won't scroll.
@@ -55,40 +146,125 @@ impl PagedReader {
         } else {
             0
         };
-        //TODO: indexes_len = rows_read
         Ok((res, indexes_len, cols_read))
     }
 
-    /// find the next "rows" new lines, starting from row_offset position in self.mmap.
+    /// Adds the part of the row shown from `column_offset`, in at most `columns` columns, to the
+    /// page, with the highlighted matches. The columns are counted as the row is shown, from its
+    /// start, so that the tabs stay aligned. Returns whether anything was shown.
+    fn push_row(&self, page: &mut Page, row: &[u8], column_offset: usize, columns: u16) -> bool {
+        let end_column = column_offset + columns as usize;
+        // The glyphs shown, with the offsets of their text in the page.
+        let mut shown = vec![];
+        let mut column = 0;
+        for glyph in Glyphs::new(row, self.render) {
+            // A combining character goes with the character before it.
+            if column > end_column || (column == end_column && glyph.width > 0) {
+                break;
+            }
+            let glyph_column = column;
+            column += glyph.width;
+            if column <= column_offset && column_offset > 0 {
+                continue;
+            }
+            // A glyph cut at the edges shows only its columns inside. A wide character can't be
+            // cut, its columns inside are left blank.
+            let skip = column_offset.saturating_sub(glyph_column);
+            let take = cmp::min(column, end_column) - glyph_column - skip;
+            let text: String = if skip == 0 && take == glyph.width {
+                glyph.text
+            } else if glyph.text.chars().count() == glyph.width {
+                glyph.text.chars().skip(skip).take(take).collect()
+            } else {
+                " ".repeat(take)
+            };
+            let text_start = page.text.len();
+            push_styled(page, &text, glyph.style);
+            shown.push((glyph.start, glyph.end, text_start, page.text.len()));
+        }
+        let (start, end) = match (shown.first(), shown.last()) {
+            (Some(first), Some(last)) => (first.0, last.1),
+            _ => return false,
+        };
+        for (pattern, color) in &self.highlights {
+            // Only the matches overlapping the part shown are needed.
+            let mut at = start.saturating_sub(pattern.max_match_len());
+            let search_end = cmp::min(row.len(), end + pattern.max_match_len());
+            while let Some((match_start, match_end)) = pattern.find_match(&row[at..search_end]) {
+                let (match_start, match_end) = (at + match_start, at + match_end);
+                if match_start >= end || match_start == match_end {
+                    break;
+                }
+                // The glyphs with a byte in the match are highlighted.
+                let glyphs: Vec<_> = shown
+                    .iter()
+                    .filter(|(start, end, ..)| *start < match_end && *end > match_start)
+                    .collect();
+                if let (Some(first), Some(last)) = (glyphs.first(), glyphs.last()) {
+                    page.spans.push(Span {
+                        start: first.2,
+                        end: last.3,
+                        style: (*color).into(),
+                    });
+                }
+                at = match_end;
+            }
+        }
+        true
+    }
+
+    /// Like get_rows_indexes, but takes the filter into account. Every row is returned together
+    /// with its number in the file.
+    fn get_numbered_rows(&mut self, rows: u16, row_offset: u64) -> io::Result<Vec<NumberedRow>> {
+        let row_offset = row_offset as usize;
+        if let Some(row_len) = self.hexdump {
+            let len = self.bytes_len();
+            return Ok((row_offset..row_offset.saturating_add(rows as usize))
+                .map(|row| (row, row.saturating_mul(row_len)))
+                .take_while(|(_row, start)| *start < len)
+                .map(|(row, start)| (row, (start, cmp::min(start + row_len, len))))
+                .collect());
+        }
+        if let Some(filter) = self.filter.as_mut() {
+            filter.fetch_rows(
+                &self.mmap[..self.len],
+                row_offset.saturating_add(rows as usize),
+            );
+            return Ok(filter
+                .rows()
+                .iter()
+                .skip(row_offset)
+                .cloned()
+                .take(rows as usize)
+                .collect());
+        }
+        let indexes = self.get_rows_indexes(rows, row_offset as u64)?;
+        Ok(indexes
+            .into_iter()
+            .enumerate()
+            .map(|(i, indexes)| (row_offset + i, indexes))
+            .collect())
+    }
+
+    /// find the next "rows" new lines, starting from the row number row_offset in self.mmap.
     fn get_rows_indexes(
         &mut self,
         rows: u16,
         row_offset: u64,
     ) -> io::Result<Vec<(StartIndex, EndIndex)>> {
-        // we need to take `row` lines, starting after `row_offset` lines.
-        // since row_offset get increased by row lines, but the count is 0-based, let's handle the special case when row_offset != 0:
+        let from_row = row_offset as usize;
+        let to_row = from_row.saturating_add(rows as usize);
 
-        let default = row_offset.saturating_sub(rows as u64) as usize;
-        //let default = cmp::max(0, row_offset as i64 - (rows as i64)) as usize;
-        let to_row = (row_offset as usize)
-            .checked_add(rows as usize)
-            .unwrap_or(default as usize);
+        if from_row < self.first_indexed_row {
+            self.fetch_previous_rows_indexes(from_row);
+        }
 
-        let file_is_all_read = self
-            .rows_indexes
-            .last()
-            .map(|(_start, end)| {
-                // if the file is empty. mmap is at least 1. But if the file is non-empty, then end and mmap.len() should match.
-                *end >= self.mmap.len() - 1
-            })
-            .unwrap_or(false);
-
-        let indexes_are_known = to_row > self.rows_indexes.len();
-        if !file_is_all_read && indexes_are_known {
+        let indexes_are_known = to_row > self.indexed_rows();
+        if !self.file_is_all_read() && indexes_are_known {
             self.fetch_missing_rows_indexes(to_row);
         }
 
-        let skip_offset = cmp::min(self.rows_indexes.len(), row_offset as usize).saturating_sub(1);
+        let skip_offset = from_row - self.first_indexed_row;
         Ok(self
             .rows_indexes
             .iter()
@@ -97,63 +273,363 @@ impl PagedReader {
             .take(rows as usize)
             .collect())
     }
-    fn fetch_missing_rows_indexes(&mut self, to_row: usize) {
-        let last_found = self
-            .rows_indexes
-            .last()
-            .map(|(_start, end)| end + 1) // end is the newline char, we need to start looking after it.
+
+    /// Number of rows before `to_row`: it's less than `to_row` only if the content ends earlier.
+    /// If a filter is set, only the filtered rows are counted.
+    pub fn rows_until(&mut self, to_row: usize) -> usize {
+        if let Some(row_len) = self.hexdump {
+            return cmp::min(self.bytes_len().div_ceil(row_len), to_row);
+        }
+        if let Some(filter) = self.filter.as_mut() {
+            filter.fetch_rows(&self.mmap[..self.len], to_row);
+            return cmp::min(filter.rows().len(), to_row);
+        }
+        if to_row > self.indexed_rows() && !self.file_is_all_read() {
+            self.fetch_missing_rows_indexes(to_row);
+        }
+        cmp::min(self.indexed_rows(), to_row)
+    }
+
+    fn file_is_all_read(&self) -> bool {
+        self.next_row_start() >= self.len
+    }
+
+    /// Offset of the first row after the indexed ones. As in POSIX, a row starts after every
+    /// new line that isn't the last byte: a new line at the end doesn't start an empty row.
+    fn next_row_start(&self) -> usize {
+        self.rows_indexes
+            .back()
+            // end is the newline char, we need to start looking after it.
+            .map(|(_start, end)| end + 1)
             .unwrap_or(0)
-            .clone();
+    }
 
-        let missing_indexes = to_row - self.rows_indexes.len();
+    fn fetch_missing_rows_indexes(&mut self, to_row: usize) {
+        let missing_indexes = to_row - self.indexed_rows();
+        // Index some more rows, for the next reads.
+        let limit = missing_indexes.saturating_mul(2);
 
-        // Left side, is inclusive.
-        let mut last = last_found;
+        let mut start = self.next_row_start();
+        for _ in 0..limit {
+            if start >= self.len {
+                break;
+            }
+            // The last row can end without a new line.
+            let end = self.mmap[start..self.len]
+                .iter()
+                .position(|c| *c == NEW_LINE_CHAR)
+                .map(|i| i + start)
+                .unwrap_or(self.len);
+            self.rows_indexes.push_back((start, end));
+            start = end + 1;
+        }
+    }
 
-        let mut limit = missing_indexes.checked_mul(2).unwrap_or(usize::MAX);
+    /// Extends the window of known rows backwards, until `from_row` is indexed.
+    fn fetch_previous_rows_indexes(&mut self, from_row: usize) {
+        while self.first_indexed_row > from_row {
+            // The window is never empty if it doesn't start from the first row.
+            let first_start = self.rows_indexes.front().map(|(start, _end)| *start);
+            let end = match first_start {
+                Some(start) if start > 0 => start - 1,
+                _ => break,
+            };
+            let start = self.mmap[..end]
+                .iter()
+                .rposition(|c| *c == NEW_LINE_CHAR)
+                .map(|i| i + 1)
+                .unwrap_or(0);
+            self.rows_indexes.push_front((start, end));
+            self.first_indexed_row -= 1;
+        }
+    }
 
-        const NEW_LINE_CHAR: u8 = b'\n';
+    /// Start-end indexes of the row number `row` of the file, if it exists.
+    fn row_bounds(&mut self, row: usize) -> Option<(StartIndex, EndIndex)> {
+        if row < self.first_indexed_row {
+            self.fetch_previous_rows_indexes(row);
+        }
+        if row >= self.indexed_rows() && !self.file_is_all_read() {
+            self.fetch_missing_rows_indexes(row + 1);
+        }
+        self.rows_indexes
+            .get(row.checked_sub(self.first_indexed_row)?)
+            .cloned()
+    }
 
-        let mmap_len = self.mmap.len();
-        // -1 because mmap is 1 even if the file is empty.
-        let is_last_char = |i| i == mmap_len - 1;
+    /// Returns the (0-based) row containing the byte at `offset`. The row boundaries are found
+    /// by searching the new lines around `offset`; if the row is outside the rows indexed so
+    /// far, the new lines in between are only counted and the window of indexed rows is moved
+    /// to start from the found row.
+    /// If a filter is set, returns the first filtered row at or after the byte at `offset`.
+    pub fn row_at_byte(&mut self, offset: usize) -> usize {
+        if let Some(row_len) = self.hexdump {
+            return cmp::min(offset, self.bytes_len().saturating_sub(1)) / row_len;
+        }
+        let offset = self.text_offset(offset);
+        self.row_at_text_byte(offset)
+    }
 
-        for (i, c) in self.mmap[last_found..] // start looking from the lastly found nl
+    /// Like row_at_byte, with the offset of a byte of the text.
+    fn row_at_text_byte(&mut self, offset: usize) -> usize {
+        if self.len == 0 {
+            return 0;
+        }
+        let offset = cmp::min(offset, self.len - 1);
+        if let Some(filter) = self.filter.as_mut() {
+            filter.fetch_to_byte(&self.mmap[..self.len], offset);
+            let row = filter
+                .rows()
+                .partition_point(|(_number, (_start, end))| *end < offset);
+            return cmp::min(row, filter.rows().len().saturating_sub(1));
+        }
+        let row_start = self.mmap[..offset]
             .iter()
-            .enumerate()
-        {
-            if *c == NEW_LINE_CHAR {
-                let found = i + last_found;
-                self.rows_indexes.push((last, found as usize));
-                last = found + 1 as usize;
-                limit -= 1;
-
-                // If I've searched for enough indexes, let's defer the search of other nl for later
-                if limit <= 0 {
-                    break;
+            .rposition(|c| *c == NEW_LINE_CHAR)
+            .map(|i| i + 1)
+            .unwrap_or(0);
+        let count_new_lines = |from: usize, to: usize| {
+            self.mmap[from..to]
+                .iter()
+                .filter(|c| **c == NEW_LINE_CHAR)
+                .count()
+        };
+
+        let window = (self.rows_indexes.front(), self.rows_indexes.back());
+        let row = match window {
+            (Some((window_start, _)), Some((_, window_end))) => {
+                if row_start < *window_start {
+                    self.first_indexed_row - count_new_lines(row_start, *window_start)
+                } else if row_start <= *window_end {
+                    let position = self
+                        .rows_indexes
+                        .partition_point(|(_start, end)| *end < row_start);
+                    return self.first_indexed_row + position;
+                } else {
+                    self.indexed_rows() + count_new_lines(window_end + 1, row_start)
                 }
-            } else if is_last_char(i) {
-                self.rows_indexes.push((last, self.mmap.len()));
             }
+            _ => count_new_lines(0, row_start),
+        };
+
+        let row_end = self.mmap[row_start..self.len]
+            .iter()
+            .position(|c| *c == NEW_LINE_CHAR)
+            .map(|i| i + row_start)
+            .unwrap_or_else(|| self.len);
+        self.rows_indexes.clear();
+        self.rows_indexes.push_back((row_start, row_end));
+        self.first_indexed_row = row;
+        row
+    }
+
+    /// Size of the content of the file in bytes.
+    pub fn bytes_len(&self) -> usize {
+        self.content().len()
+    }
+
+    /// Finds the first row after (or before, if backward) `from_row` matching `pattern`.
+    pub(crate) fn find_row(
+        &mut self,
+        pattern: &Pattern,
+        from_row: usize,
+        backward: bool,
+    ) -> Option<usize> {
+        if let Some(row_len) = self.hexdump {
+            let content = self.content();
+            let from_byte = cmp::min(from_row.saturating_mul(row_len), content.len());
+            let found = if backward {
+                pattern.rfind(&content[..from_byte])?
+            } else {
+                let start = from_byte
+                    .checked_add(row_len)
+                    .filter(|start| *start < content.len())?;
+                start + pattern.find(&content[start..])?
+            };
+            return Some(found / row_len);
+        }
+        if let Some(filter) = self.filter.as_mut() {
+            return filter.find_row(&self.mmap[..self.len], pattern, from_row, backward);
+        }
+        let found = if backward {
+            let (_start, end) = self.row_bounds(from_row.checked_sub(1)?)?;
+            pattern.rfind(&self.mmap[..end])?
+        } else {
+            let (start, _end) = self.row_bounds(from_row + 1)?;
+            start + pattern.find(&self.mmap[start..self.len])?
+        };
+        Some(self.row_at_text_byte(found))
+    }
+
+    /// Offset of the first byte of the line, counting all the lines of the file, even with a
+    /// filter.
+    pub(crate) fn line_start_byte(&mut self, line: usize) -> Option<usize> {
+        let start = self.row_bounds(line).map(|(start, _end)| start)?;
+        Some(self.file_offset(start))
+    }
+
+    /// Offset of the first byte of the line with the byte at `offset`.
+    pub(crate) fn line_start_at(&self, offset: usize) -> usize {
+        let offset = cmp::min(self.text_offset(offset), self.len);
+        let start = self.mmap[..offset]
+            .iter()
+            .rposition(|c| *c == NEW_LINE_CHAR)
+            .map_or(0, |i| i + 1);
+        self.file_offset(start)
+    }
+
+    /// Offset after the new line ending the line with the byte at `offset`.
+    pub(crate) fn line_end_byte(&self, offset: usize) -> usize {
+        let offset = cmp::min(self.text_offset(offset), self.len);
+        let end = self.mmap[offset..self.len]
+            .iter()
+            .position(|c| *c == NEW_LINE_CHAR)
+            .map_or(self.len, |i| offset + i + 1);
+        self.file_offset(end)
+    }
+
+    /// The content of the file, before decoding it.
+    pub(crate) fn content(&self) -> &[u8] {
+        match &self.file {
+            Some((mmap, _offsets)) => mmap,
+            None => self.text(),
         }
     }
 
-    pub fn cached_rows(&self) -> usize {
-        self.rows_indexes.len()
+    /// The text shown, in UTF-8.
+    pub(crate) fn text(&self) -> &[u8] {
+        &self.mmap[..self.len]
+    }
+
+    /// Offset in the text of the byte at `offset` in the file.
+    fn text_offset(&self, offset: usize) -> usize {
+        match &self.file {
+            Some((mmap, offsets)) => offsets.text_offset(mmap, offset),
+            None => offset,
+        }
+    }
+
+    /// Offset in the file of the byte at `offset` in the text.
+    fn file_offset(&self, offset: usize) -> usize {
+        match &self.file {
+            Some((mmap, offsets)) => offsets.file_offset(mmap, offset),
+            None => offset,
+        }
+    }
+
+    /// Offset of the first byte of the (possibly filtered) row.
+    pub fn row_start_byte(&mut self, row: usize) -> Option<usize> {
+        if let Some(row_len) = self.hexdump {
+            let len = self.bytes_len();
+            return Some(row.checked_mul(row_len)?).filter(|start| *start < len);
+        }
+        let start = match self.filter.as_ref() {
+            Some(filter) => filter
+                .rows()
+                .get(row)
+                .map(|(_number, (start, _end))| *start),
+            None => self.row_bounds(row).map(|(start, _end)| start),
+        };
+        Some(self.file_offset(start?))
+    }
+
+    /// Shows only the rows passing the filter, or all of them if None.
+    pub(crate) fn set_filter(&mut self, filter: Option<Filter>) {
+        let (squeeze, crlf) = (self.squeeze_blank_lines, self.crlf);
+        self.filter = match filter {
+            Some(filter) => Some(filter.squeezing_blank_lines(squeeze, crlf)),
+            None if squeeze => Some(Filter::all().squeezing_blank_lines(true, crlf)),
+            None => None,
+        };
+    }
+
+    /// Shows the consecutive blank rows as one. The line numbers are still the ones in the file.
+    pub fn set_squeeze_blank_lines(&mut self, squeeze_blank_lines: bool) {
+        self.squeeze_blank_lines = squeeze_blank_lines;
+        let filter = self.filter.take().filter(Filter::has_pattern);
+        self.set_filter(filter);
+    }
+
+    /// Highlights the matches of the patterns, each in its color.
+    pub(crate) fn set_highlights(&mut self, highlights: Vec<(Pattern, Color)>) {
+        self.highlights = highlights;
+    }
+
+    pub fn set_line_numbers(&mut self, line_numbers: bool) {
+        self.line_numbers = line_numbers;
+    }
+
+    /// Shows the content as a hexdump with `row_len` bytes per row, or as text if None. The
+    /// filter and the line numbers are not used by the hexdump.
+    pub(crate) fn set_hexdump(&mut self, row_len: Option<usize>) {
+        self.hexdump = row_len;
+    }
+
+    pub(crate) fn hexdump(&self) -> Option<usize> {
+        self.hexdump
+    }
+
+    pub fn set_hex_bytes(&mut self, hex_bytes: bool) {
+        self.render.hex_bytes = hex_bytes;
+    }
+
+    pub fn set_tab_width(&mut self, tab_width: usize) {
+        self.render.tab_width = cmp::max(1, tab_width);
+    }
+
+    pub fn set_raw_colors(&mut self, raw_colors: bool) {
+        self.render.raw_colors = raw_colors;
+    }
+
+    /// The lines end with CRLF: the `\r` at the end of the rows is not shown, and a row with
+    /// only `\r` is blank.
+    pub fn set_crlf(&mut self, crlf: bool) {
+        self.crlf = crlf;
+        let filter = self.filter.take();
+        self.set_filter(filter);
+    }
+
+    /// Number of the first row of the file after the indexed ones.
+    fn indexed_rows(&self) -> usize {
+        self.first_indexed_row + self.rows_indexes.len()
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::lesser::encoding::Encoding;
+    use crate::lesser::filter::Filter;
+    use crate::lesser::page::{Color, Span};
+    use crate::lesser::pattern::{CaseMode, Pattern};
     use crate::lesser::reader::PagedReader;
     use memmap::MmapMut;
+    use proptest::prelude::*;
     use std::io::Write;
 
+    /// A reader over the content, that can be empty.
+    fn reader(content: &[u8]) -> PagedReader {
+        if content.is_empty() {
+            return PagedReader::empty().unwrap();
+        }
+        let mut mmap = MmapMut::map_anon(content.len()).expect("Anon mmap");
+        (&mut mmap[..]).write_all(content).unwrap();
+        PagedReader::new(mmap.make_read_only().unwrap())
+    }
+
+    /// The lines of the content as in POSIX: each one ends with a new line, except maybe the last.
+    fn posix_lines(content: &[u8]) -> Vec<&[u8]> {
+        if content.is_empty() {
+            return vec![];
+        }
+        let content = content.strip_suffix(b"\n").unwrap_or(content);
+        content.split(|c| *c == b'\n').collect()
+    }
+
     #[test]
     fn test_read_file_columned() {
         let test = b"firsts\nsecond\nthird";
         let mut mmap = MmapMut::map_anon(test.len()).expect("Anon mmap");
-        (&mut mmap[..]).write(test).unwrap();
+        (&mut mmap[..]).write_all(test).unwrap();
         let mmap = mmap.make_read_only().unwrap();
         let mut paged_reader = PagedReader::new(mmap);
         let expected_rows = 2;
@@ -161,7 +637,7 @@ mod tests {
             .read_file_paged(0, 0, expected_rows, 1)
             .unwrap();
         let expected = "f\n\rs";
-        assert_eq!(expected, res);
+        assert_eq!(expected, res.text);
         assert_eq!(expected_rows as usize, rows_read);
         assert_eq!(1, cols_read);
     }
@@ -170,7 +646,7 @@ mod tests {
     fn test_read_half_file() {
         let test = b"firsts\nsecond\nthird";
         let mut mmap = MmapMut::map_anon(test.len()).expect("Anon mmap");
-        (&mut mmap[..]).write(test).unwrap();
+        (&mut mmap[..]).write_all(test).unwrap();
         let mmap = mmap.make_read_only().unwrap();
         let mut paged_reader = PagedReader::new(mmap);
         let expected_rows = 2;
@@ -178,7 +654,7 @@ mod tests {
             .read_file_paged(0, 0, expected_rows, 10)
             .unwrap();
         let expected = "firsts\n\rsecond";
-        assert_eq!(expected, res);
+        assert_eq!(expected, res.text);
         assert_eq!(expected_rows as usize, rows_read);
         assert_eq!(10, cols_read);
     }
@@ -187,7 +663,7 @@ mod tests {
     fn test_read_whole_file() {
         let test = b"firsts\nsecond\nthird";
         let mut mmap = MmapMut::map_anon(test.len()).expect("Anon mmap");
-        (&mut mmap[..]).write(test).unwrap();
+        (&mut mmap[..]).write_all(test).unwrap();
         let mmap = mmap.make_read_only().unwrap();
         let mut paged_reader = PagedReader::new(mmap);
         let expected_rows = 3;
@@ -195,7 +671,7 @@ mod tests {
             .read_file_paged(0, 0, expected_rows, 10)
             .unwrap();
         let expected = String::from_utf8_lossy(test).replace("\n", "\n\r");
-        assert_eq!(expected, res);
+        assert_eq!(expected, res.text);
         assert_eq!(expected_rows as usize, rows_read);
         assert_eq!(10, cols_read);
     }
@@ -207,21 +683,219 @@ abc"#;
         let expected = vec![(0, 0), (1, 4)];
 
         let mut mmap = MmapMut::map_anon(test.len()).expect("Anon mmap");
-        (&mut mmap[..]).write(test).unwrap();
+        (&mut mmap[..]).write_all(test).unwrap();
         let mut paged_reader = PagedReader::new(mmap.make_read_only().unwrap());
         let res = paged_reader
             .get_rows_indexes(10, 0)
             .expect("No newlines found.");
         assert_eq!(res, expected);
 
-        let no_newlines = br#""#;
-        let expected = vec![(0, 1)];
-        let mut mmap = MmapMut::map_anon(1).expect("Anon mmap");
-        (&mut mmap[..]).write(no_newlines).unwrap();
+        // No row for an empty content, nor after the last new line.
+        let mut paged_reader = PagedReader::empty().unwrap();
+        let res = paged_reader.get_rows_indexes(10, 0).unwrap();
+        assert!(res.is_empty());
+        assert_eq!(0, paged_reader.row_at_byte(10));
+
+        let test = b"a\n\n";
+        let mut mmap = MmapMut::map_anon(test.len()).expect("Anon mmap");
+        (&mut mmap[..]).write_all(test).unwrap();
         let mut paged_reader = PagedReader::new(mmap.make_read_only().unwrap());
-        let res = paged_reader
-            .get_rows_indexes(10, 0)
-            .expect("No newlines found.");
-        assert_eq!(res, expected);
+        let res = paged_reader.get_rows_indexes(10, 0).unwrap();
+        assert_eq!(vec![(0, 1), (2, 2)], res);
+    }
+
+    #[test]
+    fn test_row_at_byte() {
+        let test = b"first
+second
+third
+fourth";
+        let mut mmap = MmapMut::map_anon(test.len()).expect("Anon mmap");
+        (&mut mmap[..]).write_all(test).unwrap();
+        let mut paged_reader = PagedReader::new(mmap.make_read_only().unwrap());
+
+        // Nothing indexed yet: the row is found by counting the new lines.
+        assert_eq!(2, paged_reader.row_at_byte(15));
+        assert_eq!(3, paged_reader.indexed_rows());
+        // Before and after the indexed window.
+        assert_eq!(1, paged_reader.row_at_byte(12));
+        assert_eq!(3, paged_reader.row_at_byte(test.len() + 10));
+        assert_eq!(0, paged_reader.row_at_byte(0));
+
+        // Reading from the window extends it in both directions.
+        paged_reader.row_at_byte(13);
+        let (res, rows_read, _cols_read) = paged_reader.read_file_paged(1, 0, 3, 10).unwrap();
+        assert_eq!("second\n\rthird\n\rfourth", res.text);
+        assert_eq!(3, rows_read);
+        assert_eq!(2, paged_reader.row_at_byte(15));
+    }
+
+    #[test]
+    fn test_find_row() {
+        let test = b"a 1\nb 1\na 2\nb 2\na 3";
+        let mut mmap = MmapMut::map_anon(test.len()).expect("Anon mmap");
+        (&mut mmap[..]).write_all(test).unwrap();
+        let mut paged_reader = PagedReader::new(mmap.make_read_only().unwrap());
+        let pattern = Pattern::new("a", CaseMode::Sensitive);
+        assert_eq!(Some(2), paged_reader.find_row(&pattern, 0, false));
+        assert_eq!(Some(4), paged_reader.find_row(&pattern, 2, false));
+        assert_eq!(None, paged_reader.find_row(&pattern, 4, false));
+        assert_eq!(Some(2), paged_reader.find_row(&pattern, 4, true));
+        assert_eq!(None, paged_reader.find_row(&pattern, 0, true));
+    }
+
+    #[test]
+    fn test_read_filtered_with_line_numbers() {
+        let test = b"a 1\nb 1\na 2\nb 2\na 3";
+        let mut mmap = MmapMut::map_anon(test.len()).expect("Anon mmap");
+        (&mut mmap[..]).write_all(test).unwrap();
+        let mut paged_reader = PagedReader::new(mmap.make_read_only().unwrap());
+        paged_reader.set_line_numbers(true);
+        paged_reader.set_filter(Some(Filter::new(
+            Pattern::new("b", CaseMode::Sensitive),
+            false,
+        )));
+        let (res, rows_read, _cols_read) = paged_reader.read_file_paged(0, 0, 5, 10).unwrap();
+        assert_eq!("      2 b \n\r      4 b ", res.text);
+        assert_eq!(2, rows_read);
+        assert_eq!(2, paged_reader.rows_until(10));
+        // The row found by the search is in the filtered rows.
+        assert_eq!(
+            Some(1),
+            paged_reader.find_row(&Pattern::new("2", CaseMode::Sensitive), 0, false)
+        );
+        assert_eq!(1, paged_reader.row_at_byte(9));
+    }
+
+    #[test]
+    fn test_read_squeezed_with_line_numbers() {
+        let test = b"a\n\n\n\nb\n\nc";
+        let mut mmap = MmapMut::map_anon(test.len()).expect("Anon mmap");
+        (&mut mmap[..]).write_all(test).unwrap();
+        let mut paged_reader = PagedReader::new(mmap.make_read_only().unwrap());
+        paged_reader.set_line_numbers(true);
+        paged_reader.set_squeeze_blank_lines(true);
+        let (res, rows_read, _cols_read) = paged_reader.read_file_paged(0, 0, 10, 10).unwrap();
+        assert_eq!(
+            "      1 a\n\r      2 \n\r      5 b\n\r      6 \n\r      7 c",
+            res.text
+        );
+        assert_eq!(5, rows_read);
+        // Clearing a filter keeps squeezing.
+        paged_reader.set_filter(Some(Filter::new(
+            Pattern::new("c", CaseMode::Sensitive),
+            false,
+        )));
+        paged_reader.set_filter(None);
+        assert_eq!(5, paged_reader.rows_until(10));
+        assert_eq!(Some(5), paged_reader.row_start_byte(2));
+    }
+
+    #[test]
+    fn test_read_crlf() {
+        let test = b"a\r\nb\rc\r\n\r\nd\r";
+        let mut mmap = MmapMut::map_anon(test.len()).expect("Anon mmap");
+        (&mut mmap[..]).write_all(test).unwrap();
+        let mut paged_reader = PagedReader::new(mmap.make_read_only().unwrap());
+        paged_reader.set_crlf(true);
+        let (res, rows_read, _cols_read) = paged_reader.read_file_paged(0, 0, 5, 10).unwrap();
+        assert_eq!("a\n\rb^Mc\n\r\n\rd", res.text);
+        assert_eq!(4, rows_read);
+    }
+
+    #[test]
+    fn test_read_decoded() {
+        let test = b"\xff\xfea\x00\n\x00b\x00\n\x00";
+        let mut mmap = MmapMut::map_anon(test.len()).expect("Anon mmap");
+        (&mut mmap[..]).write_all(test).unwrap();
+        let mut paged_reader =
+            PagedReader::decoded(mmap.make_read_only().unwrap(), Encoding::Utf16Le).unwrap();
+        let (res, _rows_read, _cols_read) = paged_reader.read_file_paged(0, 0, 5, 10).unwrap();
+        assert_eq!("a\n\rb", res.text);
+        // The offsets are the ones in the file.
+        assert_eq!(10, paged_reader.bytes_len());
+        assert_eq!(1, paged_reader.row_at_byte(7));
+        assert_eq!(Some(6), paged_reader.row_start_byte(1));
+        assert_eq!(10, paged_reader.line_end_byte(6));
+        // The hexdump shows the bytes of the file.
+        paged_reader.set_hexdump(Some(8));
+        let (res, _rows_read, _cols_read) = paged_reader.read_file_paged(0, 0, 5, 80).unwrap();
+        assert!(res.text.starts_with("00000000  ff fe 61 00 0a 00"));
+    }
+
+    #[test]
+    fn test_read_rendered_columns() {
+        let mut paged_reader = reader(b"ab\tc\n\x1bX\n");
+        // The tab stops are counted from the start of the row, and a glyph cut at the left edge
+        // shows its last columns.
+        let (res, _rows_read, _cols_read) = paged_reader.read_file_paged(0, 1, 2, 9).unwrap();
+        assert_eq!("b      c\n\r[X", res.text);
+        let (res, _rows_read, _cols_read) = paged_reader.read_file_paged(0, 5, 2, 4).unwrap();
+        assert_eq!("   c\n\r", res.text);
+        // A wide character cut at the edges leaves its column inside blank.
+        let mut paged_reader = reader("漢字x".as_bytes());
+        let (res, _rows_read, _cols_read) = paged_reader.read_file_paged(0, 1, 1, 4).unwrap();
+        assert_eq!(" 字x", res.text);
+        let (res, _rows_read, _cols_read) = paged_reader.read_file_paged(0, 0, 1, 3).unwrap();
+        assert_eq!("漢 ", res.text);
+    }
+
+    #[test]
+    fn test_read_highlighted() {
+        let test = b"x ERROR y";
+        let mut mmap = MmapMut::map_anon(test.len()).expect("Anon mmap");
+        (&mut mmap[..]).write_all(test).unwrap();
+        let mut paged_reader = PagedReader::new(mmap.make_read_only().unwrap());
+        paged_reader.set_highlights(vec![
+            (Pattern::new("ERROR", CaseMode::Sensitive), Color::Red),
+            (Pattern::new("ro", CaseMode::Insensitive), Color::Yellow),
+        ]);
+        // The matches are cut at the columns shown, and can overlap.
+        let (res, _rows_read, _cols_read) = paged_reader.read_file_paged(0, 3, 1, 4).unwrap();
+        assert_eq!("RROR", res.text);
+        assert_eq!(
+            vec![
+                Span {
+                    start: 0,
+                    end: 4,
+                    style: Color::Red.into()
+                },
+                Span {
+                    start: 1,
+                    end: 3,
+                    style: Color::Yellow.into()
+                }
+            ],
+            res.spans
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn test_rows_are_posix_lines(content in "[ab\n]{0,40}", offset in 0usize..50) {
+            let content = content.as_bytes();
+            let lines = posix_lines(content);
+            let mut paged_reader = reader(content);
+            // Jumping to a byte first indexes the rows from there.
+            let expected_row = match content.len() {
+                0 => 0,
+                len => content[..offset.min(len - 1)].iter().filter(|c| **c == b'\n').count(),
+            };
+            prop_assert_eq!(expected_row, paged_reader.row_at_byte(offset));
+
+            let (res, rows_read, _cols_read) =
+                paged_reader.read_file_paged(0, 0, 100, 100).unwrap();
+            let expected: Vec<String> = lines
+                .iter()
+                .map(|line| String::from_utf8_lossy(line).to_string())
+                .collect();
+            prop_assert_eq!(expected.join("\n\r"), res.text);
+            prop_assert_eq!(lines.len(), rows_read);
+            prop_assert_eq!(lines.len(), paged_reader.rows_until(100));
+
+            let mut filter = Filter::all();
+            filter.fetch_rows(content, 100);
+            prop_assert_eq!(lines.len(), filter.rows().len());
+        }
     }
 }
@@ -0,0 +1,443 @@
+use crate::lesser::page::{Page, RawColor, Span, Style};
+use std::cmp;
+use std::convert::TryFrom;
+use unicode_width::UnicodeWidthStr;
+
+/// Default distance between the tab stops.
+const TAB_WIDTH: usize = 8;
+/// Bytes looked at to guess if the content is binary.
+const BINARY_SAMPLE_LEN: usize = 8192;
+/// Overstrikes the previous character.
+const BACKSPACE: char = '\x08';
+
+/// How the rows are shown.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct RenderOptions {
+    /// Show the control characters and the invalid UTF-8 as hex, e.g. `<1B>`.
+    pub(crate) hex_bytes: bool,
+    /// Distance between the tab stops, at least 1.
+    pub(crate) tab_width: usize,
+    /// Show the colors and the attributes set by the SGR escape sequences, e.g. `\x1b[31m`,
+    /// instead of the sequences.
+    pub(crate) raw_colors: bool,
+}
+
+impl Default for RenderOptions {
+    fn default() -> RenderOptions {
+        RenderOptions {
+            hex_bytes: false,
+            tab_width: TAB_WIDTH,
+            raw_colors: false,
+        }
+    }
+}
+
+/// A character of a row as it's shown in the terminal: the bytes from `start` to `end` of the
+/// row, written as `text` in `width` columns. A wide character, like a CJK ideograph, takes two
+/// columns and a combining character none.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Glyph {
+    pub(crate) start: usize,
+    pub(crate) end: usize,
+    pub(crate) text: String,
+    pub(crate) width: usize,
+    pub(crate) style: Style,
+}
+
+/// The glyphs of a row, as they are shown in the terminal. Nothing is written as is that the
+/// terminal would interpret, so a file can't change its state:
+/// - the control characters are shown in caret notation, e.g. `^[` for escape;
+/// - the invalid UTF-8 is replaced by `�`;
+/// - with `hex_bytes`, both are shown as the hex value of the bytes instead, e.g. `<1B>`;
+/// - tabs are expanded to spaces, up to the next tab stop from the start of the row;
+/// - the overstrikes of `man` are shown in bold (`X\bX`) and underlined (`_\bX`);
+/// - with `raw_colors`, the SGR sequences aren't shown but style the following glyphs, up to
+///   the end of the row.
+pub(crate) struct Glyphs<'a> {
+    row: &'a [u8],
+    /// Offset of the next glyph in the row.
+    at: usize,
+    /// Column of the next glyph, from the start of the row.
+    column: usize,
+    options: RenderOptions,
+    /// Style set by the SGR sequences so far.
+    sgr: Style,
+}
+
+impl<'a> Glyphs<'a> {
+    pub(crate) fn new(row: &'a [u8], options: RenderOptions) -> Glyphs<'a> {
+        Glyphs {
+            row,
+            at: 0,
+            column: 0,
+            options,
+            sgr: Style::default(),
+        }
+    }
+}
+
+impl Iterator for Glyphs<'_> {
+    type Item = Glyph;
+
+    fn next(&mut self) -> Option<Glyph> {
+        if self.options.raw_colors {
+            while let Some((params, len)) = sgr_params(&self.row[self.at..]) {
+                self.sgr = apply_sgr(self.sgr, params);
+                self.at += len;
+            }
+        }
+        let start = self.at;
+        let (c, len) = match char_at(&self.row[start..])? {
+            Ok(c) => c,
+            Err(invalid_len) => {
+                let invalid = &self.row[start..start + invalid_len];
+                let text = if self.options.hex_bytes {
+                    invalid
+                        .iter()
+                        .map(|byte| format!("<{:02X}>", byte))
+                        .collect()
+                } else {
+                    char::REPLACEMENT_CHARACTER.to_string()
+                };
+                return Some(self.glyph(start, start + invalid_len, text, Style::default()));
+            }
+        };
+        let (c, style, end) = overstrike(self.row, c, start + len);
+        let text = match c {
+            '\t' => {
+                let tab_width = self.options.tab_width;
+                " ".repeat(tab_width - self.column % tab_width)
+            }
+            c if !c.is_control() => c.to_string(),
+            c if self.options.hex_bytes => {
+                let mut buffer = [0; 4];
+                c.encode_utf8(&mut buffer)
+                    .bytes()
+                    .map(|byte| format!("<{:02X}>", byte))
+                    .collect()
+            }
+            // DEL is `^?`, the other ASCII control characters are `^@` to `^_`.
+            c if c.is_ascii() => format!("^{}", (c as u8 ^ 0x40) as char),
+            // The C1 control characters, from U+0080 to U+009F.
+            c => format!("<U+{:04X}>", c as u32),
+        };
+        Some(self.glyph(start, end, text, style))
+    }
+}
+
+impl Glyphs<'_> {
+    fn glyph(&mut self, start: usize, end: usize, text: String, style: Style) -> Glyph {
+        let style = style.over(self.sgr);
+        let width = text.width();
+        self.at = end;
+        self.column += width;
+        Glyph {
+            start,
+            end,
+            text,
+            width,
+            style,
+        }
+    }
+}
+
+/// The UTF-8 character at the start of bytes and its length, or the length of the invalid
+/// sequence there. None if there are no bytes.
+fn char_at(bytes: &[u8]) -> Option<Result<(char, usize), usize>> {
+    let bytes = &bytes[..cmp::min(4, bytes.len())];
+    let chunk = bytes.utf8_chunks().next()?;
+    Some(match chunk.valid().chars().next() {
+        Some(c) => Ok((c, c.len_utf8())),
+        None => Err(chunk.invalid().len()),
+    })
+}
+
+/// The character shown for the overstrikes of `c`, that ends at `end` in the row, their style
+/// and where they end. The backspace overstrikes the previous character: with the same one it's
+/// bold, with an underscore it's underlined, otherwise the last one is shown.
+fn overstrike(row: &[u8], mut c: char, mut end: usize) -> (char, Style, usize) {
+    let mut style = Style::default();
+    while row.get(end) == Some(&(BACKSPACE as u8)) {
+        let next = match char_at(&row[end + 1..]) {
+            Some(Ok((next, len))) if next != BACKSPACE => (next, len),
+            _ => break,
+        };
+        let (next, len) = next;
+        if next == c {
+            style.bold = true;
+        } else if c == '_' {
+            style.underline = true;
+            c = next;
+        } else if next == '_' {
+            style.underline = true;
+        } else {
+            c = next;
+        }
+        end += 1 + len;
+    }
+    (c, style, end)
+}
+
+/// The parameters of the SGR sequence at the start of bytes, e.g. `1;31` for `\x1b[1;31m`, and
+/// the length of the sequence.
+fn sgr_params(bytes: &[u8]) -> Option<(&[u8], usize)> {
+    let params = bytes.strip_prefix(b"\x1b[")?;
+    let len = params
+        .iter()
+        .position(|byte| !matches!(byte, b'0'..=b'9' | b';'))?;
+    match params[len] {
+        b'm' => Some((&params[..len], len + 3)),
+        _ => None,
+    }
+}
+
+/// The style after the SGR sequence with these parameters. The ones not understood, like
+/// blinking, are ignored.
+fn apply_sgr(mut style: Style, params: &[u8]) -> Style {
+    // An empty parameter is 0, as in `\x1b[m`.
+    let mut params = params
+        .split(|byte| *byte == b';')
+        .map(|param| std::str::from_utf8(param).ok()?.parse().ok());
+    while let Some(param) = params.next() {
+        match param.unwrap_or(0u16) {
+            0 => style = Style::default(),
+            1 => style.bold = true,
+            22 => style.bold = false,
+            4 => style.underline = true,
+            24 => style.underline = false,
+            7 => style.reverse = true,
+            27 => style.reverse = false,
+            code @ 30..=37 => style.foreground = Some(RawColor::Indexed(code as u8 - 30)),
+            code @ 90..=97 => style.foreground = Some(RawColor::Indexed(code as u8 - 82)),
+            39 => style.foreground = None,
+            code @ 40..=47 => style.background = Some(RawColor::Indexed(code as u8 - 40)),
+            code @ 100..=107 => style.background = Some(RawColor::Indexed(code as u8 - 92)),
+            49 => style.background = None,
+            code @ (38 | 48) => {
+                // `38;5;<index>` or `38;2;<r>;<g>;<b>`.
+                let mut next = || params.next().flatten().and_then(|n| u8::try_from(n).ok());
+                let color = match next() {
+                    Some(5) => next().map(RawColor::Indexed),
+                    Some(2) => match (next(), next(), next()) {
+                        (Some(r), Some(g), Some(b)) => Some(RawColor::Rgb(r, g, b)),
+                        _ => None,
+                    },
+                    _ => None,
+                };
+                if code == 38 {
+                    style.foreground = color;
+                } else {
+                    style.background = color;
+                }
+            }
+            _ => {}
+        }
+    }
+    style
+}
+
+/// Appends the text to the page, drawn with the style. The last span is extended if possible.
+pub(crate) fn push_styled(page: &mut Page, text: &str, style: Style) {
+    let start = page.text.len();
+    page.text.push_str(text);
+    if style == Style::default() {
+        return;
+    }
+    let end = page.text.len();
+    match page.spans.last_mut() {
+        Some(last) if last.end == start && last.style == style => last.end = end,
+        _ => page.spans.push(Span { start, end, style }),
+    }
+}
+
+/// Whether the content looks binary rather than text: it has a NUL byte, or many control
+/// characters or invalid UTF-8 bytes, near the beginning.
+pub(crate) fn is_binary(content: &[u8]) -> bool {
+    let mut sample = &content[..content.len().min(BINARY_SAMPLE_LEN)];
+    if sample.contains(&0) {
+        return true;
+    }
+    let mut suspicious = 0;
+    while !sample.is_empty() {
+        let (valid_len, invalid_len) = match std::str::from_utf8(sample) {
+            Ok(_) => (sample.len(), 0),
+            // A character cut at the end of the sample is fine.
+            Err(error) => (error.valid_up_to(), error.error_len().unwrap_or(0)),
+        };
+        let valid = std::str::from_utf8(&sample[..valid_len]).unwrap_or_default();
+        suspicious += valid
+            .chars()
+            .filter(|c| {
+                c.is_control() && !matches!(c, '\t' | '\n' | '\r' | '\x0c' | '\x1b' | '\x08')
+            })
+            .count();
+        suspicious += invalid_len;
+        if invalid_len == 0 {
+            break;
+        }
+        sample = &sample[valid_len + invalid_len..];
+    }
+    suspicious * 10 > content.len().min(BINARY_SAMPLE_LEN)
+}
+
+/// Whether most of the lines end with CRLF, like in the files written on Windows.
+pub(crate) fn is_crlf(content: &[u8]) -> bool {
+    let sample = &content[..content.len().min(BINARY_SAMPLE_LEN)];
+    let mut new_lines = 0;
+    let mut crlf = 0;
+    for (i, byte) in sample.iter().enumerate() {
+        if *byte == b'\n' {
+            new_lines += 1;
+            if i > 0 && sample[i - 1] == b'\r' {
+                crlf += 1;
+            }
+        }
+    }
+    crlf > 0 && crlf * 2 >= new_lines
+}
+
+/// Bytes per row of the hexdump fitting in `cols` columns: a multiple of 8, at least 8.
+pub(crate) fn hexdump_row_len(cols: u16) -> usize {
+    // The offset and two spaces, then 4 columns per byte, and the two bars around the text.
+    let bytes = (cols as usize).saturating_sub(12) / 4;
+    cmp::max(8, bytes / 8 * 8)
+}
+
+/// A row of the hexdump: the offset, the bytes in hex and the printable ASCII characters, e.g.
+/// `00000010  6c 65 73 73 0a 00 ff 20  |less... |`.
+pub(crate) fn hexdump_row(offset: usize, bytes: &[u8], row_len: usize) -> String {
+    let mut row = format!("{:08x}  ", offset);
+    for byte in bytes {
+        row.push_str(&format!("{:02x} ", byte));
+    }
+    row.push_str(&"   ".repeat(row_len.saturating_sub(bytes.len())));
+    row.push('|');
+    for byte in bytes {
+        let printable = byte.is_ascii_graphic() || *byte == b' ';
+        row.push(if printable { *byte as char } else { '.' });
+    }
+    row.push('|');
+    row
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lesser::page::{Page, RawColor, Span, Style};
+    use crate::lesser::render::{
+        hexdump_row, hexdump_row_len, is_binary, is_crlf, push_styled, Glyphs, RenderOptions,
+    };
+
+    fn render(bytes: &[u8], hex_bytes: bool) -> Page {
+        let options = RenderOptions {
+            hex_bytes,
+            ..RenderOptions::default()
+        };
+        let mut page = Page::default();
+        for glyph in Glyphs::new(bytes, options) {
+            push_styled(&mut page, &glyph.text, glyph.style);
+        }
+        page
+    }
+
+    #[test]
+    fn test_push_bytes() {
+        let bytes = b"\x1b[2Jok\x7f\tcaf\xc3\xa9 \xff\xfe\xc2\x85";
+        assert_eq!(
+            "^[[2Jok^?       café \u{fffd}\u{fffd}<U+0085>",
+            render(bytes, false).text
+        );
+        assert_eq!(
+            "<1B>[2Jok<7F>   café <FF><FE><C2><85>",
+            render(bytes, true).text
+        );
+        // A character cut at the end of the bytes.
+        assert_eq!("a\u{fffd}", render(b"a\xc3", false).text);
+    }
+
+    #[test]
+    fn test_overstrike() {
+        let bytes = b"\x08N\x08NA\x08AME x _\x08l_\x08s _\x08_";
+        let page = render(bytes, false);
+        assert_eq!("^HNAME x ls _", page.text);
+        let bold = Style {
+            bold: true,
+            ..Style::default()
+        };
+        let underline = Style {
+            underline: true,
+            ..Style::default()
+        };
+        assert_eq!(
+            vec![
+                Span {
+                    start: 2,
+                    end: 4,
+                    style: bold
+                },
+                Span {
+                    start: 9,
+                    end: 11,
+                    style: underline
+                },
+                Span {
+                    start: 12,
+                    end: 13,
+                    style: bold
+                }
+            ],
+            page.spans
+        );
+        // The backspace and `N` are two columns, the overstruck `N` one.
+        let glyphs: Vec<(usize, usize, usize)> = Glyphs::new(bytes, RenderOptions::default())
+            .map(|glyph| (glyph.start, glyph.end, glyph.width))
+            .take(3)
+            .collect();
+        assert_eq!(vec![(0, 1, 2), (1, 4, 1), (4, 7, 1)], glyphs);
+    }
+
+    #[test]
+    fn test_tab_width_and_raw_colors() {
+        let options = RenderOptions {
+            tab_width: 4,
+            raw_colors: true,
+            ..RenderOptions::default()
+        };
+        let bytes = b"a\tb\x1b[1;38;5;208mc\x1b[0m\x1b[2Jd";
+        let glyphs: Vec<_> = Glyphs::new(bytes, options).collect();
+        let text: String = glyphs.iter().map(|glyph| glyph.text.as_str()).collect();
+        // Only the SGR sequences are interpreted.
+        assert_eq!("a   bc^[[2Jd", text);
+        let c = &glyphs[3];
+        assert_eq!((16, 17), (c.start, c.end));
+        assert_eq!(
+            Style {
+                foreground: Some(RawColor::Indexed(208)),
+                bold: true,
+                ..Style::default()
+            },
+            c.style
+        );
+        assert_eq!(Style::default(), glyphs[4].style);
+    }
+
+    #[test]
+    fn test_is_crlf() {
+        assert!(is_crlf(b"a\r\nb\r\nc\n"));
+        assert!(!is_crlf(b"a\nb\r\nc\n"));
+        assert!(!is_crlf(b"a\rb\n"));
+        assert!(!is_crlf(b"no new line\r"));
+    }
+
+    #[test]
+    fn test_hexdump() {
+        assert!(is_binary(b"ELF\x00\x01"));
+        assert!(is_binary(b"\x08\x96\x01\x12\x07testing"));
+        assert!(!is_binary("caf\u{e9}\tau lait\r\n".as_bytes()));
+        assert_eq!(16, hexdump_row_len(80));
+        assert_eq!(8, hexdump_row_len(20));
+        assert_eq!(
+            "00000010  6c 65 0a 00 ff          |le...|",
+            hexdump_row(16, b"le\n\x00\xff", 8)
+        );
+    }
+}
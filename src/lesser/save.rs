@@ -0,0 +1,171 @@
+use std::path::PathBuf;
+use std::{env, fs};
+
+/// First or last line of the range saved.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Bound {
+    /// Line number in the file, from 1.
+    Line(u64),
+    /// The row on top of the screen when the mark was set.
+    Mark(char),
+}
+
+/// Lines saved, from `first` to `last` included. None is the beginning or the end of the
+/// content.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) struct Range {
+    pub(crate) first: Option<Bound>,
+    pub(crate) last: Option<Bound>,
+}
+
+/// Parses the save command, `[first,last] path`: e.g. `out.txt` saves everything, `10,20 out.txt`
+/// the lines from 10 to 20, `'a,'b out.txt` the lines between two marks and `,20 out.txt` the
+/// lines up to 20. The path can start with `~/`. Returns None without a path.
+pub(crate) fn parse_save(command: &str) -> Option<(Range, PathBuf)> {
+    let command = command.trim();
+    let (range, path) = match command.split_once(' ') {
+        Some((range, path)) => match parse_range(range) {
+            Some(range) => (range, path.trim_start()),
+            None => (Range::default(), command),
+        },
+        None if parse_range(command).is_some() => return None,
+        None => (Range::default(), command),
+    };
+    if path.is_empty() {
+        return None;
+    }
+    Some((range, expand_home(path)))
+}
+
+fn parse_range(range: &str) -> Option<Range> {
+    let (first, last) = range.split_once(',')?;
+    Some(Range {
+        first: parse_bound(first)?,
+        last: parse_bound(last)?,
+    })
+}
+
+/// None if the bound is not valid, Some(None) if it's omitted.
+fn parse_bound(bound: &str) -> Option<Option<Bound>> {
+    if bound.is_empty() {
+        return Some(None);
+    }
+    if let Some(letter) = bound.strip_prefix('\'') {
+        let mut chars = letter.chars();
+        return match (chars.next(), chars.next()) {
+            (Some(letter), None) => Some(Some(Bound::Mark(letter))),
+            _ => None,
+        };
+    }
+    bound
+        .parse()
+        .ok()
+        .filter(|line| *line > 0)
+        .map(|line| Some(Bound::Line(line)))
+}
+
+/// The path, with `~` replaced by the home directory.
+fn expand_home(path: &str) -> PathBuf {
+    let home = env::var_os("HOME").map(PathBuf::from);
+    match (path.strip_prefix('~'), home) {
+        (Some(rest), Some(home)) if rest.is_empty() || rest.starts_with('/') => {
+            home.join(rest.trim_start_matches('/'))
+        }
+        _ => PathBuf::from(path),
+    }
+}
+
+/// The text completing the path at the end of `line`, as in a shell: up to where the names of
+/// the files starting with it differ, and a `/` after a directory. The hidden files are only
+/// completed after a `.`.
+pub(crate) fn complete_path(line: &str) -> String {
+    let word = line.rsplit(' ').next().unwrap_or_default();
+    let (dir, prefix) = match word.rfind('/') {
+        Some(slash) => (&word[..=slash], &word[slash + 1..]),
+        None => ("", word),
+    };
+    let dir = if dir.is_empty() {
+        PathBuf::from(".")
+    } else {
+        expand_home(dir)
+    };
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return String::new(),
+    };
+    let matches: Vec<(String, bool)> = entries
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            let hidden = name.starts_with('.') && !prefix.starts_with('.');
+            if hidden || !name.starts_with(prefix) {
+                return None;
+            }
+            Some((name, entry.path().is_dir()))
+        })
+        .collect();
+    let (first, is_dir) = match matches.as_slice() {
+        [] => return String::new(),
+        [(name, is_dir)] => (name.as_str(), *is_dir),
+        [(name, _is_dir), ..] => (name.as_str(), false),
+    };
+    let mut common = first;
+    for (name, _is_dir) in &matches[1..] {
+        let len = common
+            .chars()
+            .zip(name.chars())
+            .take_while(|(a, b)| a == b)
+            .map(|(a, _b)| a.len_utf8())
+            .sum();
+        common = &common[..len];
+    }
+    let slash = if is_dir { "/" } else { "" };
+    format!("{}{}", &common[prefix.len()..], slash)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lesser::save::{complete_path, parse_save, Bound, Range};
+    use std::fs;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_parse_save() {
+        let range = Range {
+            first: Some(Bound::Line(10)),
+            last: Some(Bound::Mark('a')),
+        };
+        assert_eq!(
+            Some((range, PathBuf::from("out put.txt"))),
+            parse_save(" 10,'a  out put.txt")
+        );
+        let range = Range {
+            first: None,
+            last: Some(Bound::Line(20)),
+        };
+        assert_eq!(
+            Some((range, PathBuf::from("out.txt"))),
+            parse_save(",20 out.txt")
+        );
+        // Not a range, part of the path.
+        assert_eq!(
+            Some((Range::default(), PathBuf::from("0,x out.txt"))),
+            parse_save("0,x out.txt")
+        );
+        assert_eq!(None, parse_save("10,20 "));
+    }
+
+    #[test]
+    fn test_complete_path() {
+        let dir = tempdir::TempDir::new("lesser").unwrap();
+        fs::write(dir.path().join("report-1.txt"), "").unwrap();
+        fs::write(dir.path().join("report-2.txt"), "").unwrap();
+        fs::write(dir.path().join(".results"), "").unwrap();
+        fs::create_dir(dir.path().join("results")).unwrap();
+        let dir = dir.path().display();
+        assert_eq!("ort-", complete_path(&format!("1,5 {}/rep", dir)));
+        assert_eq!("ults/", complete_path(&format!("{}/res", dir)));
+        assert_eq!("", complete_path(&format!("{}/re", dir)));
+        assert_eq!("", complete_path(&format!("{}/missing/re", dir)));
+    }
+}
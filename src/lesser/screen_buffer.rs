@@ -0,0 +1,252 @@
+use crate::lesser::page::{Page, Style};
+use std::io::{self, Write};
+use termion::{clear, cursor};
+use unicode_width::UnicodeWidthChar;
+
+/// A column of the terminal: the character shown there, with its combining characters, and its
+/// style. The text is empty in the column after a wide character, that covers both.
+type Cell = (String, Style);
+
+/// Keeps the page shown in the terminal, to write only the cells changed by the next page
+/// instead of clearing the whole screen every time.
+pub(crate) struct ScreenBuffer {
+    /// Rows shown, as cells.
+    rows: Vec<Vec<Cell>>,
+    /// Size of the page area, as (cols, rows). None if the content of the screen is unknown.
+    size: Option<(u16, u16)>,
+}
+
+impl ScreenBuffer {
+    pub(crate) fn new() -> ScreenBuffer {
+        ScreenBuffer {
+            rows: vec![],
+            size: None,
+        }
+    }
+
+    /// Writes `page` in the first `rows` rows of the terminal. Returns true if the whole page
+    /// area was redrawn, because the content of the screen was unknown or its size changed.
+    pub(crate) fn draw<W: Write + ?Sized>(
+        &mut self,
+        out: &mut W,
+        page: &Page,
+        rows: u16,
+        cols: u16,
+    ) -> io::Result<bool> {
+        let mut new_rows: Vec<Vec<Cell>> = page
+            .cells()
+            .into_iter()
+            .take(rows as usize)
+            .map(|row| row_cells(row, cols))
+            .collect();
+        new_rows.resize(rows as usize, vec![]);
+
+        let redraw_all = self.size != Some((cols, rows));
+        if redraw_all {
+            self.rows = vec![vec![]; rows as usize];
+            for row in 0..rows {
+                write!(out, "{}{}", cursor::Goto(1, row + 1), clear::CurrentLine)?;
+            }
+        } else {
+            self.scroll_region(out, &new_rows, rows)?;
+        }
+
+        for (i, (old, new)) in self.rows.iter().zip(new_rows.iter()).enumerate() {
+            write_row_changes(out, old, new, i as u16)?;
+        }
+        self.rows = new_rows;
+        self.size = Some((cols, rows));
+        Ok(redraw_all)
+    }
+
+    /// Forgets the content of the screen, so the next draw rewrites everything.
+    pub(crate) fn invalidate(&mut self) {
+        self.size = None;
+    }
+
+    /// If the new page is the old one moved by a row, lets the terminal scroll the page area,
+    /// so only the row that entered the screen has to be written.
+    fn scroll_region<W: Write + ?Sized>(
+        &mut self,
+        out: &mut W,
+        new_rows: &[Vec<Cell>],
+        rows: u16,
+    ) -> io::Result<()> {
+        let rows = rows as usize;
+        if rows < 2 || self.rows == new_rows {
+            return Ok(());
+        }
+        // Restrict the scroll to the page, so the prompt line stays where it is.
+        let set_region = format!("\x1b[1;{}r", rows);
+        let reset_region = "\x1b[r";
+        if self.rows[1..] == new_rows[..rows - 1] {
+            // Scroll the content up by one row.
+            write!(out, "{}\x1b[1S{}", set_region, reset_region)?;
+            self.rows.remove(0);
+            self.rows.push(vec![]);
+        } else if self.rows[..rows - 1] == new_rows[1..] {
+            // Scroll the content down by one row.
+            write!(out, "{}\x1b[1T{}", set_region, reset_region)?;
+            self.rows.pop();
+            self.rows.insert(0, vec![]);
+        }
+        Ok(())
+    }
+}
+
+/// The cells of a row of characters, up to `cols` columns. A wide character that doesn't fit in
+/// the last column isn't shown.
+fn row_cells(chars: Vec<(char, Style)>, cols: u16) -> Vec<Cell> {
+    let mut cells: Vec<Cell> = vec![];
+    for (c, style) in chars {
+        match c.width().unwrap_or(1) {
+            0 => match cells
+                .iter_mut()
+                .rev()
+                .find(|(text, _style)| !text.is_empty())
+            {
+                Some((text, _style)) => text.push(c),
+                // Nothing to combine with at the start of the row.
+                None => cells.push((format!(" {}", c), style)),
+            },
+            width => {
+                if cells.len() + width > cols as usize {
+                    break;
+                }
+                cells.push((c.to_string(), style));
+                if width > 1 {
+                    cells.push((String::new(), style));
+                }
+            }
+        }
+    }
+    cells
+}
+
+/// Writes the cells of the row that are different between old and new.
+fn write_row_changes<W: Write + ?Sized>(
+    out: &mut W,
+    old: &[Cell],
+    new: &[Cell],
+    row: u16,
+) -> io::Result<()> {
+    if old == new {
+        return Ok(());
+    }
+    let mut first_change = old
+        .iter()
+        .zip(new.iter())
+        .take_while(|(old, new)| old == new)
+        .count();
+    // A wide character is written from its first column.
+    if first_change > 0
+        && new
+            .get(first_change)
+            .is_some_and(|(text, _)| text.is_empty())
+    {
+        first_change -= 1;
+    }
+    // If the length didn't change, the cells at the end can be equal as well.
+    let last_change = if old.len() == new.len() {
+        new.len()
+            - old
+                .iter()
+                .rev()
+                .zip(new.iter().rev())
+                .take_while(|(old, new)| old == new)
+                .count()
+    } else {
+        new.len()
+    };
+    let mut changed = String::new();
+    let mut current_style = Style::default();
+    for (text, style) in &new[first_change..last_change] {
+        changed.push_str(&style.escape_from(current_style));
+        current_style = *style;
+        changed.push_str(text);
+    }
+    changed.push_str(&Style::default().escape_from(current_style));
+    write!(
+        out,
+        "{}{}",
+        cursor::Goto(first_change as u16 + 1, row + 1),
+        changed
+    )?;
+    if new.len() < old.len() {
+        write!(out, "{}", clear::UntilNewline)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lesser::page::{Color, Page, Span};
+    use crate::lesser::screen_buffer::ScreenBuffer;
+
+    fn draw(buffer: &mut ScreenBuffer, page: &str) -> (String, bool) {
+        let mut out = vec![];
+        let redrawn = buffer.draw(&mut out, &Page::from(page), 3, 10).unwrap();
+        (String::from_utf8(out).unwrap(), redrawn)
+    }
+
+    #[test]
+    fn test_draw_changes() {
+        let mut buffer = ScreenBuffer::new();
+        let (_out, redrawn) = draw(&mut buffer, "first\n\rsecond\n\rthird");
+        assert!(redrawn);
+        assert_eq!(
+            ("".to_string(), false),
+            draw(&mut buffer, "first\n\rsecond\n\rthird")
+        );
+
+        // Only the changed cells are written.
+        let (out, _redrawn) = draw(&mut buffer, "fiRst\n\rsecond\n\rthird");
+        assert_eq!("\x1b[1;3HR", out);
+        let (out, _redrawn) = draw(&mut buffer, "fiRst\n\rsecant\n\rthird");
+        assert_eq!("\x1b[2;4Hant", out);
+        let (out, _redrawn) = draw(&mut buffer, "fiRst\n\rsec\n\rthird");
+        assert_eq!("\x1b[2;4H\x1b[K", out);
+
+        // A cell changing only its color is written again.
+        let page = Page {
+            text: "fiRst\n\rsec\n\rthird".to_string(),
+            spans: vec![Span {
+                start: 1,
+                end: 2,
+                style: Color::Red.into(),
+            }],
+        };
+        let mut out = vec![];
+        buffer.draw(&mut out, &page, 3, 10).unwrap();
+        assert_eq!(
+            "\x1b[1;2H\x1b[38;5;0m\x1b[48;5;1mi\x1b[39m\x1b[49m",
+            String::from_utf8(out).unwrap()
+        );
+
+        buffer.invalidate();
+        let (_out, redrawn) = draw(&mut buffer, "first\n\rsec\n\rthird");
+        assert!(redrawn);
+    }
+
+    #[test]
+    fn test_draw_wide_characters() {
+        let mut buffer = ScreenBuffer::new();
+        // The ideographs take two columns, the last one doesn't fit.
+        let (out, _redrawn) = draw(&mut buffer, "a漢b\n\re\u{301}\n\r1234567漢字");
+        assert!(out.ends_with("\x1b[1;1Ha漢b\x1b[2;1He\u{301}\x1b[3;1H1234567漢"));
+        let (out, _redrawn) = draw(&mut buffer, "a字b\n\re\u{301}\n\r1234567漢");
+        assert_eq!("\x1b[1;2H字", out);
+        let (out, _redrawn) = draw(&mut buffer, "a字c\n\re\n\r1234567漢");
+        assert_eq!("\x1b[1;4Hc\x1b[2;1He", out);
+    }
+
+    #[test]
+    fn test_draw_scroll() {
+        let mut buffer = ScreenBuffer::new();
+        draw(&mut buffer, "1\n\r2\n\r3");
+        let (out, _redrawn) = draw(&mut buffer, "2\n\r3\n\r4");
+        assert_eq!("\x1b[1;3r\x1b[1S\x1b[r\x1b[3;1H4", out);
+        let (out, _redrawn) = draw(&mut buffer, "1\n\r2\n\r3");
+        assert_eq!("\x1b[1;3r\x1b[1T\x1b[r\x1b[1;1H1", out);
+    }
+}
@@ -1,15 +1,90 @@
+use crate::lesser::filter::Filter;
+use crate::lesser::history::FileState;
+use crate::lesser::page::{Color, Page, COLORS};
+use crate::lesser::pattern::{CaseMode, Pattern};
 use crate::lesser::reader::PagedReader;
+use crate::lesser::render::hexdump_row_len;
+use crate::lesser::save::{Bound, Range};
 use log::debug;
 use std::cmp;
-use std::io::Result;
+use std::collections::HashMap;
+use std::fs::OpenOptions;
+use std::io::{self, Result, Write};
+use std::path::Path;
 
-type PageToPrint = Option<String>;
+type PageToPrint = Option<Page>;
+/// A position: the offset of the first byte of the top row, and the first column displayed.
+/// Unlike the number of the row, the byte stays on the same line when the rows shown change,
+/// e.g. with a filter or the hexdump.
+pub(crate) type Position = (u64, u64);
+
+/// Row of the screen where the target of a jump (a search, `:b` or `Np`) is shown, like
+/// `less -j`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum JumpTarget {
+    /// A row counted from 1 at the top, or from -1 at the bottom if negative.
+    Line(i64),
+    /// A fraction of the screen height, e.g. 0.5 for the middle.
+    Fraction(f64),
+}
+
+impl JumpTarget {
+    /// Parses the value of `less -j`: `N`, `-N` or `.N`.
+    pub fn parse(value: &str) -> Option<JumpTarget> {
+        if value.contains('.') {
+            let fraction: f64 = value.parse().ok()?;
+            return Some(JumpTarget::Fraction(fraction))
+                .filter(|_| (0.0..=1.0).contains(&fraction));
+        }
+        value.parse().ok().map(JumpTarget::Line)
+    }
+
+    /// Row of the screen, from 0, where the target is shown among `rows`.
+    fn screen_row(self, rows: u16) -> u64 {
+        let rows = rows as i64;
+        let row = match self {
+            JumpTarget::Line(line) if line < 0 => rows + line,
+            JumpTarget::Line(line) => line - 1,
+            JumpTarget::Fraction(fraction) => (rows as f64 * fraction) as i64,
+        };
+        row.clamp(0, cmp::max(0, rows - 1)) as u64
+    }
+}
+
+/// The special mark returning to the position before the last jump.
+pub(crate) const LAST_POSITION_MARK: char = '\'';
+/// The mark set by clicking a row with the mouse.
+pub(crate) const MOUSE_MARK: char = '#';
+
+/// Columns moved by a horizontal scroll.
+const HORIZONTAL_MOVEMENT: u64 = 10;
 
 pub struct ScreenMoveHandler {
-    /// Row-wise position currently displayed
+    /// First row displayed
     row_offset: u64,
-    /// Column-wise position currently displayed
+    /// First column displayed
     col_offset: u64,
+    /// Rows moved by a page scroll, set with `Nz`/`Nw`. None means the screen height.
+    window_size: Option<u64>,
+    /// Rows moved by a half page scroll, set with `Nd`/`Nu`. None means half the screen height.
+    half_window_size: Option<u64>,
+    /// Positions saved with `m<letter>`.
+    marks: HashMap<char, Position>,
+    /// Position before the last jump (search, goto, mark), used by the `''` mark.
+    last_position: Option<Position>,
+    /// Last pattern searched, and if the search was backward.
+    last_search: Option<(String, bool)>,
+    /// Row of the last match, and the first row displayed after moving to it. The match isn't
+    /// on top if the view stopped at the end of the file: the next search starts from the match
+    /// as long as the view doesn't move.
+    last_match: Option<(u64, u64)>,
+    /// How the case is matched by the searches and the filters.
+    case: CaseMode,
+    /// Where the targets of the jumps are shown. None for the top row for the searches, and
+    /// the middle for the other jumps.
+    jump_target: Option<JumpTarget>,
+    /// Patterns highlighted, each with its color.
+    highlights: Vec<(String, Color)>,
     paged_reader: PagedReader,
 }
 
@@ -18,125 +93,843 @@ impl ScreenMoveHandler {
         ScreenMoveHandler {
             row_offset: 0,
             col_offset: 0,
+            window_size: None,
+            half_window_size: None,
+            marks: HashMap::new(),
+            last_position: None,
+            last_search: None,
+            last_match: None,
+            case: CaseMode::Sensitive,
+            jump_target: None,
+            highlights: vec![],
             paged_reader,
         }
     }
-    /// The first page
+    /// The first page. Starts from the position restored from a previous session, if any.
+    /// An empty content is an empty page.
     pub(crate) fn initial_screen(&mut self, rows: u16, cols: u16) -> Result<PageToPrint> {
-        let (page, rows_read, cols_read) = self.paged_reader.read_file_paged(0, 0, rows, cols)?;
-        self.row_offset += rows_read as u64;
-        self.col_offset += cols_read as u64;
-        let ret = if rows_read > 0 { Some(page) } else { None };
-        Ok(ret)
+        Ok(self
+            .move_to_row(self.row_offset, rows, cols)?
+            .or_else(|| Some(Page::default())))
     }
 
-    /// Doesn't trigger any movement, just rereads the current screen.
+    /// Doesn't trigger any movement, just rereads the current screen, e.g. after a resize: the
+    /// top row and the column stay the same.
     pub(crate) fn reload(&mut self, rows: u16, cols: u16) -> Result<PageToPrint> {
-        // reset the index back to the start of the line:
-        self.col_offset = 0;
-        // Re read this page:
-        self.row_offset = self.row_offset.saturating_sub(rows as u64);
+        Ok(self
+            .read_page(rows, cols)?
+            .or_else(|| Some(Page::default())))
+    }
 
-        let (page, rows_read, cols_read) =
+    /// Reads the page starting at the current position.
+    fn read_page(&mut self, rows: u16, cols: u16) -> Result<PageToPrint> {
+        self.fit_hexdump(cols);
+        let (page, rows_read, _cols_read) =
             self.paged_reader
                 .read_file_paged(self.row_offset, self.col_offset, rows, cols)?;
-        self.row_offset += rows_read as u64;
-        self.col_offset += cols_read as u64;
-
         Ok(if rows_read > 0 { Some(page) } else { None })
     }
 
-    // X axis: read the page and moves the col offset position
-    // from self.col_offset to self.col_offset+ cols_read.
-    fn move_x(&mut self, rows: u16, cols: u16) -> Result<PageToPrint> {
-        // Re read the same rows
-        let fixed_row_offset = self.row_offset.saturating_sub(rows as u64);
-
-        let (page, _rows_read, cols_read) =
-            self.paged_reader
-                .read_file_paged(fixed_row_offset, self.col_offset, rows, cols)?;
-        self.col_offset += cols_read as u64;
-        let ret = if cols_read > 0 { Some(page) } else { None };
-        Ok(ret)
-    }
+    // X axis:
 
-    /// Move left one column
+    /// Move left by HORIZONTAL_MOVEMENT columns
     pub(crate) fn move_left(&mut self, rows: u16, cols: u16) -> Result<PageToPrint> {
-        const MOVEMENT: u64 = 10;
-        debug!("Received move right request");
-
-        // we're not moving by rows:
-        self.col_offset = self.col_offset.saturating_sub(cols as u64 + MOVEMENT);
-        self.move_x(rows, cols)
+        debug!("Received move left request");
+        if self.col_offset == 0 {
+            return Ok(None);
+        }
+        self.col_offset = self.col_offset.saturating_sub(HORIZONTAL_MOVEMENT);
+        self.read_page(rows, cols)
     }
 
+    /// Move right by HORIZONTAL_MOVEMENT columns, unless the page would be empty.
     pub(crate) fn move_right(&mut self, rows: u16, cols: u16) -> Result<PageToPrint> {
-        const MOVEMENT: u64 = 10;
-        debug!("Received move right request by {}", MOVEMENT);
-        // This is used to avoid going back one screen if the move_x has returned None
-        // (e.g it hasn't read anything).
-        let old_offset = self.col_offset;
-        self.col_offset = self.col_offset.saturating_sub(cols as u64 - MOVEMENT);
-        let ret = self.move_x(rows, cols)?;
-        if ret.is_none() && old_offset != self.col_offset {
-            self.col_offset = old_offset;
+        debug!("Received move right request by {}", HORIZONTAL_MOVEMENT);
+        let (page, _rows_read, cols_read) = self.paged_reader.read_file_paged(
+            self.row_offset,
+            self.col_offset + HORIZONTAL_MOVEMENT,
+            rows,
+            cols,
+        )?;
+        // If horizontal scrolling hasn't returned any char, then won't scroll.
+        if cols_read == 0 {
+            return Ok(None);
         }
-        Ok(ret)
+        self.col_offset += HORIZONTAL_MOVEMENT;
+        Ok(Some(page))
     }
 
     // Y axis:
 
-    fn move_y(&mut self, rows: u16, cols: u16) -> Result<PageToPrint> {
-        let fixed_col_offset = self.col_offset.saturating_sub(cols as u64);
-        let (page, rows_read, _cols_read) =
-            self.paged_reader
-                .read_file_paged(self.row_offset, fixed_col_offset, rows, cols)?;
-        self.row_offset = cmp::min(self.row_offset, self.paged_reader.cached_rows() as u64);
-        self.row_offset += rows_read as u64;
+    /// Moves the view so that `top_row` is the first row displayed. The view doesn't go past
+    /// the end of the file: the last row is at most at the bottom of the screen.
+    fn move_to_row(&mut self, top_row: u64, rows: u16, cols: u16) -> Result<PageToPrint> {
+        let to_row = top_row.saturating_add(rows as u64);
+        let available_rows = self.paged_reader.rows_until(to_row as usize) as u64;
+        self.row_offset = cmp::min(top_row, available_rows.saturating_sub(rows as u64));
+        self.read_page(rows, cols)
+    }
 
-        Ok(if rows_read > 0 { Some(page) } else { None })
+    /// Scrolls up or down by `movement` rows. Returns None if the view can't move.
+    fn scroll(&mut self, movement: i64, rows: u16, cols: u16) -> Result<PageToPrint> {
+        let old_offset = self.row_offset;
+        let top_row = if movement < 0 {
+            self.row_offset.saturating_sub(movement.unsigned_abs())
+        } else {
+            self.row_offset.saturating_add(movement as u64)
+        };
+        let page = self.move_to_row(top_row, rows, cols)?;
+        Ok(page.filter(|_| self.row_offset != old_offset))
+    }
+
+    fn page_size(&self, rows: u16) -> i64 {
+        self.window_size.unwrap_or(rows as u64) as i64
+    }
+
+    fn half_page_size(&self, rows: u16) -> i64 {
+        let half_page = cmp::max(rows as u64 / 2, 1);
+        self.half_window_size.unwrap_or(half_page) as i64
     }
 
     pub(crate) fn move_down_page(&mut self, rows: u16, cols: u16) -> Result<PageToPrint> {
         debug!("Received move down page request");
-        self.move_y(rows, cols)
+        self.scroll(self.page_size(rows), rows, cols)
     }
     pub(crate) fn move_up_page(&mut self, rows: u16, cols: u16) -> Result<PageToPrint> {
-        debug!("Received move up request");
-        // I need to read not from the beginning of this page, but from the beginning of the last page. Thus * 2.
-        self.row_offset = self.row_offset.saturating_sub(rows as u64 * 2);
-        self.move_y(rows, cols)
+        debug!("Received move up page request");
+        self.scroll(-self.page_size(rows), rows, cols)
+    }
+
+    /// Moves down one window. If window_size is set, it becomes the size of the window.
+    pub(crate) fn move_down_window(
+        &mut self,
+        window_size: Option<u64>,
+        rows: u16,
+        cols: u16,
+    ) -> Result<PageToPrint> {
+        self.window_size = window_size.or(self.window_size);
+        self.move_down_page(rows, cols)
+    }
+
+    /// Moves up one window. If window_size is set, it becomes the size of the window.
+    pub(crate) fn move_up_window(
+        &mut self,
+        window_size: Option<u64>,
+        rows: u16,
+        cols: u16,
+    ) -> Result<PageToPrint> {
+        self.window_size = window_size.or(self.window_size);
+        self.move_up_page(rows, cols)
+    }
+
+    /// Moves down half a window. If size is set, it becomes the size of the half window.
+    pub(crate) fn move_down_half_page(
+        &mut self,
+        size: Option<u64>,
+        rows: u16,
+        cols: u16,
+    ) -> Result<PageToPrint> {
+        debug!("Received move down half page request");
+        self.half_window_size = size.or(self.half_window_size);
+        self.scroll(self.half_page_size(rows), rows, cols)
+    }
+
+    /// Moves up half a window. If size is set, it becomes the size of the half window.
+    pub(crate) fn move_up_half_page(
+        &mut self,
+        size: Option<u64>,
+        rows: u16,
+        cols: u16,
+    ) -> Result<PageToPrint> {
+        debug!("Received move up half page request");
+        self.half_window_size = size.or(self.half_window_size);
+        self.scroll(-self.half_page_size(rows), rows, cols)
     }
 
     pub(crate) fn move_up(&mut self, rows: u16, cols: u16) -> Result<PageToPrint> {
         debug!("Received move up request");
-        self.row_offset = self.row_offset.saturating_sub(rows as u64 + 1);
-        self.move_y(rows, cols)
+        self.scroll(-1, rows, cols)
     }
 
     pub(crate) fn move_down(&mut self, rows: u16, cols: u16) -> Result<PageToPrint> {
-        const MOVEMENT: u64 = 1;
-        debug!("Received move up request");
-        // This is used to avoid going back one screen if the move_x has returned None
-        // (e.g it hasn't read anything).
-        let old_offset = self.row_offset;
-        self.row_offset = self.row_offset.saturating_sub(rows as u64 - MOVEMENT);
-        let ret = self.move_y(rows, cols)?;
-        if ret.is_none() && old_offset != self.row_offset {
-            self.row_offset = old_offset;
-        }
-        Ok(ret)
+        debug!("Received move down request");
+        self.scroll(1, rows, cols)
     }
 
     pub(crate) fn move_to_top(&mut self, rows: u16, cols: u16) -> Result<PageToPrint> {
         debug!("Received move to beginning request");
-        self.row_offset = 0;
-        self.move_y(rows, cols)
+        self.save_last_position();
+        self.move_to_row(0, rows, cols)
     }
 
     pub(crate) fn move_to_end(&mut self, rows: u16, cols: u16) -> Result<PageToPrint> {
         debug!("Received move to end request");
-        self.row_offset = u64::MAX - rows as u64;
-        self.move_y(rows, cols)
+        self.save_last_position();
+        self.move_to_row(u64::MAX, rows, cols)
+    }
+
+    /// Moves the view to the row containing the byte at `offset`, shown in the middle or at the
+    /// jump target.
+    pub(crate) fn goto_byte(&mut self, offset: u64, rows: u16, cols: u16) -> Result<PageToPrint> {
+        debug!("Received goto byte {} request", offset);
+        let offset = cmp::min(offset, usize::MAX as u64) as usize;
+        let row = self.paged_reader.row_at_byte(offset) as u64;
+        let target = self
+            .jump_target
+            .map_or(rows as u64 / 2, |target| target.screen_row(rows));
+        self.save_last_position();
+        self.move_to_row(row.saturating_sub(target), rows, cols)
+    }
+
+    /// Moves the view to the row found at `percent`% of the file.
+    pub(crate) fn goto_percent(
+        &mut self,
+        percent: u64,
+        rows: u16,
+        cols: u16,
+    ) -> Result<PageToPrint> {
+        debug!("Received goto {}% request", percent);
+        let percent = cmp::min(percent, 100);
+        let offset = self.paged_reader.bytes_len() as u64 * percent / 100;
+        self.goto_byte(offset, rows, cols)
+    }
+
+    /// Searches the pattern starting from the row after the jump target, the first one shown by
+    /// default, and moves the view to show the row found there. An empty pattern repeats the
+    /// last search.
+    pub(crate) fn search(
+        &mut self,
+        pattern: &str,
+        backward: bool,
+        rows: u16,
+        cols: u16,
+    ) -> Result<PageToPrint> {
+        debug!("Received search {:?} request", pattern);
+        if !pattern.is_empty() {
+            self.last_search = Some((pattern.to_string(), backward));
+            self.last_match = None;
+        }
+        self.search_next(false, rows, cols)
+    }
+
+    /// Moves to the next match of the last search. If reverse is set, the search goes in the
+    /// opposite direction of the last one.
+    pub(crate) fn search_next(
+        &mut self,
+        reverse: bool,
+        rows: u16,
+        cols: u16,
+    ) -> Result<PageToPrint> {
+        let (pattern, backward) = match self.last_search.as_ref() {
+            Some((pattern, backward)) => (Pattern::new(pattern, self.case), *backward != reverse),
+            None => return Ok(None),
+        };
+        let target = self.jump_target.map_or(0, |target| target.screen_row(rows));
+        let from_row = match self.last_match {
+            Some((row, row_offset)) if row_offset == self.row_offset => row,
+            _ => self.row_offset + target,
+        };
+        let row = match self
+            .paged_reader
+            .find_row(&pattern, from_row as usize, backward)
+        {
+            Some(row) => row as u64,
+            None => return Ok(None),
+        };
+        self.save_last_position();
+        let page = self.move_to_row(row.saturating_sub(target), rows, cols)?;
+        self.last_match = Some((row, self.row_offset));
+        Ok(page)
+    }
+
+    /// Shows only the rows matching the pattern, or the ones not matching it if the pattern
+    /// starts with `!`. An empty pattern shows all the rows again.
+    pub(crate) fn filter(&mut self, pattern: &str, rows: u16, cols: u16) -> Result<PageToPrint> {
+        debug!("Received filter {:?} request", pattern);
+        // Keep showing the same part of the file.
+        let top_byte = self
+            .paged_reader
+            .row_start_byte(self.row_offset as usize)
+            .unwrap_or(0);
+        let filter = match pattern.strip_prefix('!') {
+            _ if pattern.is_empty() => None,
+            Some(pattern) => Some(Filter::new(Pattern::new(pattern, self.case), true)),
+            None => Some(Filter::new(Pattern::new(pattern, self.case), false)),
+        };
+        self.paged_reader.set_filter(filter);
+        self.last_match = None;
+        let row = self.paged_reader.row_at_byte(top_byte) as u64;
+        // If no rows passed the filter, show an empty page.
+        Ok(self
+            .move_to_row(row, rows, cols)?
+            .or_else(|| Some(Page::default())))
+    }
+
+    /// Shows the content as a hexdump or as text, keeping the same bytes on top.
+    pub(crate) fn set_hexdump(
+        &mut self,
+        hexdump: bool,
+        rows: u16,
+        cols: u16,
+    ) -> Result<PageToPrint> {
+        debug!("Received hexdump {} request", hexdump);
+        let top_byte = self
+            .paged_reader
+            .row_start_byte(self.row_offset as usize)
+            .unwrap_or(0);
+        let row_len = Some(hexdump_row_len(cols)).filter(|_| hexdump);
+        self.paged_reader.set_hexdump(row_len);
+        self.last_match = None;
+        self.col_offset = 0;
+        let row = self.paged_reader.row_at_byte(top_byte) as u64;
+        self.move_to_row(row, rows, cols)
+    }
+
+    /// Changes the bytes per row of the hexdump if the width of the screen changed. The top
+    /// row becomes the one with the same bytes.
+    fn fit_hexdump(&mut self, cols: u16) {
+        let old_len = match self.paged_reader.hexdump() {
+            Some(old_len) => old_len,
+            None => return,
+        };
+        let new_len = hexdump_row_len(cols);
+        if new_len != old_len {
+            self.paged_reader.set_hexdump(Some(new_len));
+            self.row_offset = self.row_offset * old_len as u64 / new_len as u64;
+            self.last_match = None;
+        }
+    }
+
+    /// Remembers the current position, before jumping somewhere else.
+    fn save_last_position(&mut self) {
+        self.last_position = Some(self.position());
+    }
+
+    fn position(&mut self) -> Position {
+        let top_byte = self
+            .paged_reader
+            .row_start_byte(self.row_offset as usize)
+            .unwrap_or(0);
+        (top_byte as u64, self.col_offset)
+    }
+
+    /// Sets how the case is matched by the searches, the filters and the highlights.
+    pub(crate) fn set_case(&mut self, case: CaseMode) {
+        self.case = case;
+        self.compile_highlights();
+    }
+
+    pub(crate) fn set_jump_target(&mut self, jump_target: JumpTarget) {
+        self.jump_target = Some(jump_target);
+    }
+
+    /// Switches between `case` and the case sensitive search, like the `-i` and `-I` commands
+    /// of less. Returns the new mode.
+    pub(crate) fn toggle_case(&mut self, case: CaseMode) -> CaseMode {
+        self.case = if self.case == case {
+            CaseMode::Sensitive
+        } else {
+            case
+        };
+        self.compile_highlights();
+        self.case
+    }
+
+    /// Highlights the matches of the pattern in `color`, or in the first color not used yet.
+    pub(crate) fn highlight(
+        &mut self,
+        pattern: &str,
+        color: Option<Color>,
+        rows: u16,
+        cols: u16,
+    ) -> Result<PageToPrint> {
+        debug!("Received highlight {:?} request", pattern);
+        if pattern.is_empty() {
+            return Ok(None);
+        }
+        self.highlights
+            .retain(|(highlighted, _color)| highlighted != pattern);
+        let color = color.unwrap_or_else(|| {
+            let unused = COLORS
+                .iter()
+                .map(|(color, _name)| *color)
+                .find(|color| self.highlights.iter().all(|(_pattern, used)| used != color));
+            unused.unwrap_or(COLORS[self.highlights.len() % COLORS.len()].0)
+        });
+        self.highlights.push((pattern.to_string(), color));
+        self.compile_highlights();
+        self.read_page(rows, cols)
+    }
+
+    /// Stops highlighting the pattern, or all the patterns if None. Returns None if the pattern
+    /// wasn't highlighted.
+    pub(crate) fn remove_highlight(
+        &mut self,
+        pattern: Option<&str>,
+        rows: u16,
+        cols: u16,
+    ) -> Result<PageToPrint> {
+        debug!("Received remove highlight {:?} request", pattern);
+        let highlights = self.highlights.len();
+        match pattern {
+            Some(pattern) => self
+                .highlights
+                .retain(|(highlighted, _color)| highlighted != pattern),
+            None => self.highlights.clear(),
+        }
+        if highlights == self.highlights.len() {
+            return Ok(None);
+        }
+        self.compile_highlights();
+        self.read_page(rows, cols)
+    }
+
+    fn compile_highlights(&mut self) {
+        let highlights = self
+            .highlights
+            .iter()
+            .map(|(pattern, color)| (Pattern::new(pattern, self.case), *color))
+            .collect();
+        self.paged_reader.set_highlights(highlights);
+    }
+
+    /// Saves the current position in the mark `letter`.
+    pub(crate) fn set_mark(&mut self, letter: char) {
+        debug!("Received set mark {} request", letter);
+        let position = self.position();
+        self.marks.insert(letter, position);
+    }
+
+    /// Saves in the mark `letter` the position having the given row of the screen on top. Rows
+    /// after the end of the content are ignored.
+    pub(crate) fn set_mark_at_row(&mut self, letter: char, screen_row: u16) {
+        debug!("Received set mark {} at row {} request", letter, screen_row);
+        let row = self.row_offset as usize + screen_row as usize;
+        if let Some(top_byte) = self.paged_reader.row_start_byte(row) {
+            self.marks
+                .insert(letter, (top_byte as u64, self.col_offset));
+        }
+    }
+
+    /// Moves the row clicked on the screen to the top, and marks it with `MOUSE_MARK`. Like
+    /// the other jumps, `''` goes back. Returns None for the rows after the end of the content.
+    pub(crate) fn click(&mut self, screen_row: u16, rows: u16, cols: u16) -> Result<PageToPrint> {
+        debug!("Received click on row {} request", screen_row);
+        let row = self.row_offset + screen_row as u64;
+        if self.paged_reader.row_start_byte(row as usize).is_none() {
+            return Ok(None);
+        }
+        self.set_mark_at_row(MOUSE_MARK, screen_row);
+        self.save_last_position();
+        self.move_to_row(row, rows, cols)
+    }
+
+    /// Goes back to the position saved in the mark `letter`. Returns None if the mark is not set.
+    pub(crate) fn goto_mark(&mut self, letter: char, rows: u16, cols: u16) -> Result<PageToPrint> {
+        debug!("Received goto mark {} request", letter);
+        let position = match self.mark_position(letter) {
+            Some(position) => position,
+            None => return Ok(None),
+        };
+        self.save_last_position();
+        self.move_to_position(position, rows, cols)
+    }
+
+    fn mark_position(&self, letter: char) -> Option<Position> {
+        if letter == LAST_POSITION_MARK {
+            self.last_position
+        } else {
+            self.marks.get(&letter).cloned()
+        }
+    }
+
+    /// Writes the lines of the range to the file at `path`, or the whole content if the range
+    /// has no bounds. An existing file is only replaced with `overwrite`. Returns the number of
+    /// bytes written.
+    pub(crate) fn save(&mut self, range: Range, path: &Path, overwrite: bool) -> Result<usize> {
+        debug!("Received save {:?} to {:?} request", range, path);
+        let start = match range.first {
+            Some(bound) => {
+                let byte = self.bound_byte(bound)?;
+                self.paged_reader.line_start_at(byte)
+            }
+            None => 0,
+        };
+        let end = match range.last {
+            Some(bound) => {
+                let byte = self.bound_byte(bound)?;
+                self.paged_reader.line_end_byte(byte)
+            }
+            None => self.paged_reader.bytes_len(),
+        };
+        if end < start {
+            return Err(io::Error::other("the range ends before its start"));
+        }
+        let mut options = OpenOptions::new();
+        options.write(true);
+        if overwrite {
+            options.create(true).truncate(true);
+        } else {
+            options.create_new(true);
+        }
+        let mut file = options.open(path)?;
+        file.write_all(&self.paged_reader.content()[start..end])?;
+        Ok(end - start)
+    }
+
+    /// Offset of a byte in the line of the bound: its first byte for a line number, the first
+    /// byte of the top row for a mark. Lines are the ones of the file, even with a filter.
+    fn bound_byte(&mut self, bound: Bound) -> Result<usize> {
+        let start = match bound {
+            Bound::Line(line) => (line as usize)
+                .checked_sub(1)
+                .and_then(|line| self.paged_reader.line_start_byte(line)),
+            Bound::Mark(letter) => self
+                .mark_position(letter)
+                .map(|(top_byte, _col)| top_byte as usize),
+        };
+        start.ok_or_else(|| {
+            io::Error::other(match bound {
+                Bound::Line(line) => format!("there is no line {}", line),
+                Bound::Mark(letter) => format!("the mark {} is not set", letter),
+            })
+        })
+    }
+
+    fn move_to_position(
+        &mut self,
+        position: Position,
+        rows: u16,
+        cols: u16,
+    ) -> Result<PageToPrint> {
+        let (top_byte, col_offset) = position;
+        self.col_offset = col_offset;
+        let top_byte = cmp::min(top_byte, usize::MAX as u64) as usize;
+        let row = self.paged_reader.row_at_byte(top_byte) as u64;
+        self.move_to_row(row, rows, cols)
+    }
+
+    /// Current position and marks, to be saved across sessions.
+    pub(crate) fn file_state(&mut self) -> FileState {
+        FileState {
+            position: self.position(),
+            marks: self.marks.clone(),
+        }
+    }
+
+    /// Restores position and marks from a previous session. The position is used by initial_screen.
+    pub(crate) fn restore_file_state(&mut self, state: FileState) {
+        let (top_byte, col_offset) = state.position;
+        let top_byte = cmp::min(top_byte, usize::MAX as u64) as usize;
+        self.row_offset = self.paged_reader.row_at_byte(top_byte) as u64;
+        self.col_offset = col_offset;
+        self.marks = state.marks;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lesser::encoding::Encoding;
+    use crate::lesser::page::{Color, Page};
+    use crate::lesser::pattern::CaseMode;
+    use crate::lesser::reader::PagedReader;
+    use crate::lesser::save::{Bound, Range};
+    use crate::lesser::screen_move_handler::{
+        JumpTarget, ScreenMoveHandler, LAST_POSITION_MARK, MOUSE_MARK,
+    };
+    use memmap::MmapMut;
+    use std::fs;
+    use std::io::Write;
+
+    fn handler_for(test: &[u8]) -> ScreenMoveHandler {
+        let mut mmap = MmapMut::map_anon(test.len()).expect("Anon mmap");
+        (&mut mmap[..]).write_all(test).unwrap();
+        ScreenMoveHandler::new(PagedReader::new(mmap.make_read_only().unwrap()))
+    }
+
+    #[test]
+    fn test_marks() {
+        let mut handler = handler_for(b"1\n2\n3\n4\n5\n6\n7\n8\n9");
+        assert_eq!(
+            Some(Page::from("1\n\r2")),
+            handler.initial_screen(2, 10).unwrap()
+        );
+        handler.set_mark('a');
+        assert_eq!(None, handler.goto_mark('b', 2, 10).unwrap());
+
+        handler.move_to_end(2, 10).unwrap();
+        assert_eq!(
+            Some(Page::from("1\n\r2")),
+            handler.goto_mark('a', 2, 10).unwrap()
+        );
+        assert_eq!(
+            Some(Page::from("8\n\r9")),
+            handler.goto_mark(LAST_POSITION_MARK, 2, 10).unwrap()
+        );
+        assert_eq!(
+            Some(Page::from("1\n\r2")),
+            handler.goto_mark(LAST_POSITION_MARK, 2, 10).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_click() {
+        let mut handler = handler_for(b"1\n2\n3\n4\n5");
+        handler.initial_screen(2, 10).unwrap();
+        assert_eq!(Some(Page::from("2\n\r3")), handler.click(1, 2, 10).unwrap());
+        assert_eq!(
+            Some(Page::from("1\n\r2")),
+            handler.goto_mark(LAST_POSITION_MARK, 2, 10).unwrap()
+        );
+        assert_eq!(
+            Some(Page::from("2\n\r3")),
+            handler.goto_mark(MOUSE_MARK, 2, 10).unwrap()
+        );
+        // Nothing to click after the end of the content.
+        handler.move_to_end(3, 10).unwrap();
+        assert_eq!(None, handler.click(3, 4, 10).unwrap());
+    }
+
+    #[test]
+    fn test_marks_with_filter() {
+        let mut handler = handler_for(b"a 1\nb 2\na 3\nb 4\na 5\nb 6");
+        handler.initial_screen(2, 10).unwrap();
+        handler.move_down(2, 10).unwrap();
+        handler.set_mark('a');
+        handler.set_mark_at_row('b', 1);
+        // The rows shown change, the marks stay on their line.
+        handler.filter("b", 2, 10).unwrap();
+        assert_eq!(
+            Some(Page::from("b 4\n\rb 6")),
+            handler.goto_mark('b', 2, 10).unwrap()
+        );
+        assert_eq!(
+            Some(Page::from("b 2\n\rb 4")),
+            handler.goto_mark('a', 2, 10).unwrap()
+        );
+        handler.filter("", 2, 10).unwrap();
+        assert_eq!(
+            Some(Page::from("a 3\n\rb 4")),
+            handler.goto_mark('b', 2, 10).unwrap()
+        );
+        // The position saved is in the same line.
+        let state = handler.file_state();
+        assert_eq!((8, 0), state.position);
+        let mut restored = handler_for(b"a 1\nb 2\na 3\nb 4\na 5\nb 6");
+        restored.restore_file_state(state);
+        assert_eq!(
+            Some(Page::from("a 3\n\rb 4")),
+            restored.initial_screen(2, 10).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_scroll() {
+        let mut handler = handler_for(b"1\n2\n3\n4\n5\n6\n7\n8\n9");
+        handler.initial_screen(4, 10).unwrap();
+        assert_eq!(None, handler.move_up(4, 10).unwrap());
+        assert_eq!(
+            Some(Page::from("3\n\r4\n\r5\n\r6")),
+            handler.move_down_half_page(None, 4, 10).unwrap()
+        );
+        assert_eq!(
+            Some(Page::from("4\n\r5\n\r6\n\r7")),
+            handler.move_down_window(Some(1), 4, 10).unwrap()
+        );
+        // The window size is kept.
+        assert_eq!(
+            Some(Page::from("3\n\r4\n\r5\n\r6")),
+            handler.move_up_page(4, 10).unwrap()
+        );
+        assert_eq!(
+            Some(Page::from("6\n\r7\n\r8\n\r9")),
+            handler.move_down_half_page(Some(3), 4, 10).unwrap()
+        );
+        // The last row is at most at the bottom of the screen.
+        assert_eq!(None, handler.move_down(4, 10).unwrap());
+        assert_eq!(
+            Some(Page::from("3\n\r4\n\r5\n\r6")),
+            handler.move_up_half_page(None, 4, 10).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_search_next_on_last_page() {
+        let mut handler = handler_for(b"1\n2\n3\nx 4\n5\nx 6\n7\nx 8\n9");
+        handler.initial_screen(4, 10).unwrap();
+        assert_eq!(
+            Some(Page::from("x 4\n\r5\n\rx 6\n\r7")),
+            handler.search("x", false, 4, 10).unwrap()
+        );
+        // The last two matches are on the last page, that can't scroll further.
+        let last_page = Some(Page::from("x 6\n\r7\n\rx 8\n\r9"));
+        assert_eq!(last_page, handler.search_next(false, 4, 10).unwrap());
+        assert_eq!(last_page, handler.search_next(false, 4, 10).unwrap());
+        assert_eq!(None, handler.search_next(false, 4, 10).unwrap());
+        assert_eq!(last_page, handler.search_next(true, 4, 10).unwrap());
+        assert_eq!(
+            Some(Page::from("x 4\n\r5\n\rx 6\n\r7")),
+            handler.search_next(true, 4, 10).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_jump_target() {
+        assert_eq!(Some(JumpTarget::Fraction(0.5)), JumpTarget::parse(".5"));
+        assert_eq!(Some(JumpTarget::Line(-1)), JumpTarget::parse("-1"));
+        assert_eq!(None, JumpTarget::parse("1.5"));
+        assert_eq!(3, JumpTarget::Line(-1).screen_row(4));
+        assert_eq!(2, JumpTarget::Fraction(0.5).screen_row(4));
+
+        let mut handler = handler_for(b"1\nx 2\n3\n4\nx 5\n6\n7\n8\n9");
+        handler.set_jump_target(JumpTarget::Line(2));
+        handler.initial_screen(4, 10).unwrap();
+        // The search starts after the second row, and shows the match there.
+        assert_eq!(
+            Some(Page::from("4\n\rx 5\n\r6\n\r7")),
+            handler.search("x", false, 4, 10).unwrap()
+        );
+        assert_eq!(
+            Some(Page::from("x 5\n\r6\n\r7\n\r8")),
+            handler.goto_byte(15, 4, 10).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_reload_keeps_position() {
+        let content: String = "abcde"
+            .chars()
+            .map(|c| format!("{}\n", c.to_string().repeat(20)))
+            .collect();
+        let mut handler = handler_for(content.as_bytes());
+        handler.initial_screen(2, 10).unwrap();
+        handler.move_down(2, 10).unwrap();
+        handler.move_right(2, 10).unwrap();
+        // Bigger and smaller, as after resizing the terminal.
+        assert_eq!(
+            Some(Page::from("bbbbbbbbbb\n\rcccccccccc\n\rdddddddddd")),
+            handler.reload(3, 16).unwrap()
+        );
+        assert_eq!(Some(Page::from("bbbbbb")), handler.reload(1, 6).unwrap());
+    }
+
+    #[test]
+    fn test_highlight() {
+        let mut handler = handler_for(b"trace 42 ERROR\nok");
+        handler.initial_screen(2, 20).unwrap();
+        handler.highlight("42", None, 2, 20).unwrap();
+        handler.highlight("error", Some(Color::Red), 2, 20).unwrap();
+        // The first unused color.
+        let page = handler.highlight("ok", None, 2, 20).unwrap().unwrap();
+        let colors: Vec<Color> = page
+            .spans
+            .iter()
+            .flat_map(|span| span.style.color)
+            .collect();
+        assert_eq!(vec![Color::Yellow, Color::Green], colors);
+        handler.set_case(CaseMode::Smart);
+        let page = handler
+            .remove_highlight(Some("42"), 2, 20)
+            .unwrap()
+            .unwrap();
+        let colors: Vec<Color> = page
+            .spans
+            .iter()
+            .flat_map(|span| span.style.color)
+            .collect();
+        assert_eq!(vec![Color::Red, Color::Green], colors);
+        assert_eq!(None, handler.remove_highlight(Some("42"), 2, 20).unwrap());
+        let page = handler.remove_highlight(None, 2, 20).unwrap().unwrap();
+        assert!(page.spans.is_empty());
+    }
+
+    #[test]
+    fn test_hexdump() {
+        let content: Vec<u8> = (0..100).collect();
+        let mut handler = handler_for(&content);
+        handler.initial_screen(2, 80).unwrap();
+        let page = handler.set_hexdump(true, 2, 80).unwrap().unwrap();
+        assert!(page.text.starts_with("00000000  00 01 02"));
+        handler.move_down(2, 80).unwrap();
+        // Narrower: 8 bytes per row, the 16 bytes on top are still shown.
+        let page = handler.reload(2, 40).unwrap().unwrap();
+        assert!(page.text.starts_with("00000010  10 11"));
+        let page = handler.move_to_end(2, 40).unwrap().unwrap();
+        assert!(page.text.ends_with("|`abc|"));
+    }
+
+    #[test]
+    fn test_save() {
+        let mut handler = handler_for(b"1\n2\n3\n4\n5");
+        let dir = tempdir::TempDir::new("lesser").unwrap();
+        let path = dir.path().join("saved");
+        let range = Range {
+            first: Some(Bound::Line(2)),
+            last: Some(Bound::Mark('a')),
+        };
+        // The mark isn't set yet.
+        assert!(handler.save(range, &path, false).is_err());
+        handler.initial_screen(2, 10).unwrap();
+        handler.move_down(2, 10).unwrap();
+        handler.move_down(2, 10).unwrap();
+        handler.set_mark('a');
+        assert_eq!(4, handler.save(range, &path, false).unwrap());
+        assert_eq!("2\n3\n", fs::read_to_string(&path).unwrap());
+        // The file is only replaced when asked.
+        assert!(handler.save(Range::default(), &path, false).is_err());
+        assert_eq!(9, handler.save(Range::default(), &path, true).unwrap());
+        let range = Range {
+            first: Some(Bound::Line(4)),
+            last: None,
+        };
+        assert_eq!(3, handler.save(range, &path, true).unwrap());
+        assert_eq!("4\n5", fs::read_to_string(&path).unwrap());
+
+        // The bytes saved are the ones of the file, not the decoded text.
+        let latin1 = b"caf\xe9\nth\xe9\n";
+        let mut mmap = MmapMut::map_anon(latin1.len()).unwrap();
+        mmap.copy_from_slice(latin1);
+        let paged_reader =
+            PagedReader::decoded(mmap.make_read_only().unwrap(), Encoding::Latin1).unwrap();
+        let mut handler = ScreenMoveHandler::new(paged_reader);
+        let range = Range {
+            first: Some(Bound::Line(2)),
+            last: None,
+        };
+        assert_eq!(4, handler.save(range, &path, true).unwrap());
+        assert_eq!(b"th\xe9\n", &fs::read(&path).unwrap()[..]);
+    }
+
+    #[test]
+    fn test_save_marks_with_filter() {
+        let mut handler = handler_for(b"a 1\nb 2\na 3\nb 4\na 5\nb 6");
+        let dir = tempdir::TempDir::new("lesser").unwrap();
+        let path = dir.path().join("saved");
+        handler.initial_screen(2, 10).unwrap();
+        handler.filter("b", 2, 10).unwrap();
+        handler.set_mark('a');
+        handler.move_down(2, 10).unwrap();
+        handler.set_mark('b');
+        // The lines between the marks are saved, the filter only changes what is shown.
+        let range = Range {
+            first: Some(Bound::Mark('a')),
+            last: Some(Bound::Mark('b')),
+        };
+        assert_eq!(12, handler.save(range, &path, false).unwrap());
+        assert_eq!("b 2\na 3\nb 4\n", fs::read_to_string(&path).unwrap());
+
+        // In the hexdump, with 8 bytes per row, the mark is in the middle of a line.
+        let mut handler = handler_for(b"line one\nline two\nline three");
+        handler.initial_screen(1, 40).unwrap();
+        handler.set_hexdump(true, 1, 40).unwrap();
+        handler.move_down(1, 40).unwrap();
+        handler.move_down(1, 40).unwrap();
+        handler.set_mark('c');
+        let range = Range {
+            first: Some(Bound::Mark('c')),
+            last: Some(Bound::Mark('c')),
+        };
+        assert_eq!(9, handler.save(range, &path, true).unwrap());
+        assert_eq!("line two\n", fs::read_to_string(&path).unwrap());
     }
 }
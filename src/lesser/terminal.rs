@@ -0,0 +1,135 @@
+use std::io::{self, stdout, Stdout, Write};
+use std::os::unix::io::AsRawFd;
+use std::sync::Mutex;
+use termion::{clear, cursor, screen};
+
+/// Enables the mouse reporting, with the SGR (1006) encoding.
+const ENTER_MOUSE_SEQUENCE: &str = "\x1b[?1000h\x1b[?1002h\x1b[?1015h\x1b[?1006h";
+const EXIT_MOUSE_SEQUENCE: &str = "\x1b[?1006l\x1b[?1015l\x1b[?1002l\x1b[?1000l";
+
+/// What is needed to give the terminal back, shared with the panic hook.
+struct SavedState {
+    /// Attributes of the terminal before entering raw mode.
+    termios: libc::termios,
+    mouse: bool,
+    alternate_screen: bool,
+}
+
+/// Set while the terminal is set up by lesser.
+static SAVED_STATE: Mutex<Option<SavedState>> = Mutex::new(None);
+
+/// The terminal used to show the pages: in raw mode, with the cursor hidden, optionally on the
+/// alternate screen and with mouse reporting. It can be left and entered again, e.g. to suspend
+/// the process. It's restored when dropped.
+pub(crate) struct Terminal {
+    out: Stdout,
+    termios: libc::termios,
+    mouse: bool,
+    alternate_screen: bool,
+}
+
+impl Terminal {
+    pub(crate) fn new(mouse: bool, alternate_screen: bool) -> io::Result<Terminal> {
+        let out = stdout();
+        let termios = get_termios(&out)?;
+        let mut terminal = Terminal {
+            out,
+            termios,
+            mouse,
+            alternate_screen,
+        };
+        terminal.enter()?;
+        Ok(terminal)
+    }
+
+    /// Gives the terminal back as it was before lesser started.
+    pub(crate) fn leave(&mut self) -> io::Result<()> {
+        let state = lock_saved_state().take();
+        match state {
+            Some(state) => write_leave(&mut self.out, &state),
+            None => Ok(()),
+        }
+    }
+
+    /// Sets up the terminal again, after leave.
+    pub(crate) fn enter(&mut self) -> io::Result<()> {
+        let mut raw = self.termios;
+        unsafe { libc::cfmakeraw(&mut raw) };
+        set_termios(&self.out, &raw)?;
+        *lock_saved_state() = Some(SavedState {
+            termios: self.termios,
+            mouse: self.mouse,
+            alternate_screen: self.alternate_screen,
+        });
+        if self.alternate_screen {
+            write!(self.out, "{}", screen::ToAlternateScreen)?;
+        }
+        write!(self.out, "{}", cursor::Hide)?;
+        if self.mouse {
+            write!(self.out, "{}", ENTER_MOUSE_SEQUENCE)?;
+        }
+        self.out.flush()
+    }
+}
+
+impl Write for Terminal {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.out.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        let _ = self.leave();
+    }
+}
+
+/// Gives the terminal back, if lesser has set it up. It's meant for the panic hook, where
+/// the Terminal can't be reached.
+pub(crate) fn restore() {
+    let state = lock_saved_state().take();
+    if let Some(state) = state {
+        let _ = write_leave(&mut stdout(), &state);
+    }
+}
+
+fn lock_saved_state() -> std::sync::MutexGuard<'static, Option<SavedState>> {
+    // A panic while holding the lock can't leave the state half updated.
+    SAVED_STATE
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+fn write_leave(out: &mut Stdout, state: &SavedState) -> io::Result<()> {
+    if state.mouse {
+        write!(out, "{}", EXIT_MOUSE_SEQUENCE)?;
+    }
+    write!(out, "{}", cursor::Show)?;
+    if state.alternate_screen {
+        write!(out, "{}", screen::ToMainScreen)?;
+    } else {
+        // The page stays on the screen, the prompt line is left for the shell.
+        write!(out, "\r{}", clear::CurrentLine)?;
+    }
+    out.flush()?;
+    set_termios(out, &state.termios)
+}
+
+fn get_termios(out: &Stdout) -> io::Result<libc::termios> {
+    let mut termios = unsafe { std::mem::zeroed() };
+    if unsafe { libc::tcgetattr(out.as_raw_fd(), &mut termios) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(termios)
+}
+
+fn set_termios(out: &Stdout, termios: &libc::termios) -> io::Result<()> {
+    if unsafe { libc::tcsetattr(out.as_raw_fd(), libc::TCSANOW, termios) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
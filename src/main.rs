@@ -1,14 +1,166 @@
-use crate::lesser::run;
+use crate::lesser::{run, CaseMode, Config, Encoding, JumpTarget, Options};
 use clap::Clap;
+use std::env;
 use std::path::PathBuf;
 mod lesser;
 
+/// Holds the flags from the command line, or from the `LESSER` environment variable.
 #[derive(Clap)]
 #[clap(version = "0.0.1")]
 struct Opts {
     #[clap(takes_value = true)]
     /// name of the file to read
     filename: Option<PathBuf>,
+    /// restore and save the reading position and the marks of the file (default)
+    #[clap(long, overrides_with = "no-history")]
+    history: bool,
+    /// don't restore nor save the reading position and the marks of the file
+    #[clap(long, overrides_with = "history")]
+    no_history: bool,
+    /// show the line numbers
+    #[clap(short = 'N', long, overrides_with = "no-line-numbers")]
+    line_numbers: bool,
+    /// don't show the line numbers (default)
+    #[clap(long, overrides_with = "line-numbers")]
+    no_line_numbers: bool,
+    /// scroll with the mouse wheel. Selecting text may require holding shift
+    #[clap(long, overrides_with = "no-mouse")]
+    mouse: bool,
+    /// leave the mouse to the terminal (default)
+    #[clap(long, overrides_with = "mouse")]
+    no_mouse: bool,
+    /// print the file and exit if it fits in one screen
+    #[clap(short = 'F', long, overrides_with = "no-quit-if-one-screen")]
+    quit_if_one_screen: bool,
+    /// always show the file in the pager (default)
+    #[clap(long, overrides_with = "quit-if-one-screen")]
+    no_quit_if_one_screen: bool,
+    /// use the alternate screen, that is cleared on exit (default)
+    #[clap(long, overrides_with = "no-alternate-screen")]
+    alternate_screen: bool,
+    /// don't use the alternate screen: the last page stays on the screen on exit
+    #[clap(short = 'X', long, overrides_with = "alternate-screen")]
+    no_alternate_screen: bool,
+    /// show consecutive blank lines as a single one
+    #[clap(short = 's', long, overrides_with = "no-squeeze-blank-lines")]
+    squeeze_blank_lines: bool,
+    /// show all the blank lines (default)
+    #[clap(long, overrides_with = "squeeze-blank-lines")]
+    no_squeeze_blank_lines: bool,
+    /// show the control characters and the invalid UTF-8 as hex, e.g. <1B>, instead of ^[ and �
+    #[clap(long, overrides_with = "no-hex-bytes")]
+    hex_bytes: bool,
+    /// show the control characters in caret notation, e.g. ^[ (default)
+    #[clap(long, overrides_with = "hex-bytes")]
+    no_hex_bytes: bool,
+    /// distance between the tab stops, 8 by default
+    #[clap(short = 'x', long, parse(try_from_str = parse_tab_width))]
+    tab_width: Option<usize>,
+    /// show the colors and the attributes set by the escape sequences of the file, e.g. of ls --color
+    #[clap(short = 'R', long, overrides_with = "no-raw-colors")]
+    raw_colors: bool,
+    /// show the escape sequences in caret notation (default)
+    #[clap(long, overrides_with = "raw-colors")]
+    no_raw_colors: bool,
+    /// row of the screen where the searches and the jumps show their target: N from the top, -N
+    /// from the bottom or .N for a fraction of the screen, e.g. .5 for the middle
+    #[clap(short = 'j', long, allow_hyphen_values = true, parse(try_from_str = parse_jump_target))]
+    jump_target: Option<JumpTarget>,
+    /// show the file as a hexdump. Without --hexdump nor --no-hexdump, lesser asks for binary files
+    #[clap(long, overrides_with = "no-hexdump")]
+    hexdump: bool,
+    /// show the file as text, even if it's binary
+    #[clap(long, overrides_with = "hexdump")]
+    no_hexdump: bool,
+    /// encoding of the file: utf-8, utf-16le, utf-16be, latin1 or windows-1252. Guessed by default
+    #[clap(long, parse(try_from_str = parse_encoding))]
+    encoding: Option<Encoding>,
+    /// ignore the case in searches, unless the pattern has uppercase letters
+    #[clap(short = 'i', long, overrides_with_all = &["ignore-case-always", "case-sensitive"])]
+    ignore_case: bool,
+    /// always ignore the case in searches
+    #[clap(short = 'I', long, overrides_with_all = &["ignore-case", "case-sensitive"])]
+    ignore_case_always: bool,
+    /// match the case in searches (default)
+    #[clap(long, overrides_with_all = &["ignore-case", "ignore-case-always"])]
+    case_sensitive: bool,
+}
+
+impl Opts {
+    /// The options given by the flags.
+    fn config(&self) -> Config {
+        Config {
+            history: flag(self.history, self.no_history),
+            line_numbers: flag(self.line_numbers, self.no_line_numbers),
+            mouse: flag(self.mouse, self.no_mouse),
+            quit_if_one_screen: flag(self.quit_if_one_screen, self.no_quit_if_one_screen),
+            alternate_screen: flag(self.alternate_screen, self.no_alternate_screen),
+            squeeze_blank_lines: flag(self.squeeze_blank_lines, self.no_squeeze_blank_lines),
+            hex_bytes: flag(self.hex_bytes, self.no_hex_bytes),
+            tab_width: self.tab_width,
+            raw_colors: flag(self.raw_colors, self.no_raw_colors),
+            jump_target: self.jump_target,
+            hexdump: flag(self.hexdump, self.no_hexdump),
+            encoding: self.encoding,
+            case: match (
+                self.ignore_case,
+                self.ignore_case_always,
+                self.case_sensitive,
+            ) {
+                (true, _, _) => Some(CaseMode::Smart),
+                (_, true, _) => Some(CaseMode::Insensitive),
+                (_, _, true) => Some(CaseMode::Sensitive),
+                _ => None,
+            },
+        }
+    }
+}
+
+fn parse_encoding(name: &str) -> Result<Encoding, String> {
+    Encoding::from_name(name).ok_or_else(|| format!("unknown encoding {}", name))
+}
+
+fn parse_tab_width(width: &str) -> Result<usize, String> {
+    match width.parse() {
+        Ok(width) if width > 0 => Ok(width),
+        _ => Err(format!("invalid tab width {}", width)),
+    }
+}
+
+fn parse_jump_target(target: &str) -> Result<JumpTarget, String> {
+    JumpTarget::parse(target).ok_or_else(|| format!("invalid jump target {}", target))
+}
+
+fn flag(enabled: bool, disabled: bool) -> Option<bool> {
+    match (enabled, disabled) {
+        (true, _) => Some(true),
+        (_, true) => Some(false),
+        _ => None,
+    }
+}
+
+/// The options in the `LESSER` environment variable, written as on the command line.
+fn env_config() -> Result<Config, String> {
+    let flags = match env::var("LESSER") {
+        Ok(flags) => flags,
+        Err(_) => return Ok(Config::default()),
+    };
+    let args = std::iter::once("lesser").chain(flags.split_whitespace());
+    let opts = Opts::try_parse_from(args).map_err(|error| format!("LESSER: {}", error))?;
+    if opts.filename.is_some() {
+        return Err("LESSER: only options are allowed".to_string());
+    }
+    Ok(opts.config())
+}
+
+/// The options of less in the `LESS` environment variable, that lesser understands.
+fn less_config() -> Config {
+    let value = env::var("LESS").unwrap_or_default();
+    let (config, warnings) = Config::from_less(&value);
+    for warning in warnings {
+        eprintln!("Warning: LESS: {}", warning);
+    }
+    config
 }
 
 fn main() {
@@ -19,7 +171,39 @@ fn main() {
     env_logger::init_from_env(env);
 
     let opts: Opts = Opts::parse();
-    if let Err(error) = run(opts.filename) {
+    // The command line comes first, then LESSER, LESS and the config file.
+    let file_config = match Config::default_path() {
+        Some(path) => Config::load(&path).map_err(|error| error.to_string()),
+        None => Ok(Config::default()),
+    };
+    let config = file_config
+        .map(|file| less_config().or(file))
+        .and_then(|config| Ok(env_config()?.or(config)));
+    let config = match config {
+        Ok(config) => opts.config().or(config),
+        Err(error) => {
+            eprintln!("Error: {}", error);
+            std::process::exit(1);
+        }
+    };
+    let options = Options {
+        use_history: config.history.unwrap_or(true),
+        line_numbers: config.line_numbers.unwrap_or(false),
+        mouse: config.mouse.unwrap_or(false),
+        quit_if_one_screen: config.quit_if_one_screen.unwrap_or(false),
+        alternate_screen: config.alternate_screen.unwrap_or(true),
+        case: config.case.unwrap_or(CaseMode::Sensitive),
+        squeeze_blank_lines: config.squeeze_blank_lines.unwrap_or(false),
+        hex_bytes: config.hex_bytes.unwrap_or(false),
+        tab_width: config.tab_width,
+        raw_colors: config.raw_colors.unwrap_or(false),
+        jump_target: config.jump_target,
+        hexdump: config.hexdump,
+        encoding: config.encoding,
+    };
+    if let Err(error) = run(opts.filename, options) {
+        // The terminal has been restored by now.
         eprintln!("Error: {}", error);
+        std::process::exit(1);
     };
 }